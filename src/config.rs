@@ -3,19 +3,67 @@
 //! If no custom configuration is specified, we fall back to the default
 //! configuration which is included as bytes in the compiled binary.
 
+use crate::file_ops::Placement;
+use crate::regex_ops::SignSpacing;
+use crate::template::DEFAULT_TEMPLATE;
 use crate::CError;
 use crate::CommentSign;
 use glob::Pattern;
 use once_cell::sync::OnceCell;
-use serde::Deserialize;
-use std::collections::HashMap;
-use std::path::Path;
+use serde::de::{MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_yaml::{Mapping, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
 
 static CFG: OnceCell<Config> = OnceCell::new();
 
-#[derive(Debug, Deserialize)]
+pub const DEFAULT_COPYRIGHT_SYMBOL: &str = "(C)";
+
+/// Fields which every config has to contain, possibly through includes.
+const REQUIRED_FIELDS: [&str; 3] = ["comment_sign_map", "ignore_files", "ignore_dirs"];
+
+/// Built-in configuration, used if no config file is given.
+pub const DEFAULT_CONFIG: &str = include_str!("./default_cfg.yml");
+
+/// Additions to the default configuration in tests, e.g. the comment sign of
+/// the `.editorconfig` fixtures and a file type to skip.
+#[cfg(test)]
+const TEST_CONFIG: &str = "comment_sign_map:\n  .editorconfig: \"#\"\n  md: ~\n";
+
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Config {
+    #[serde(default)]
+    template: Option<String>,
+    #[serde(serialize_with = "serialize_sorted")]
     comment_sign_map: HashMap<String, CommentSign>,
+    #[serde(
+        default,
+        serialize_with = "serialize_sorted",
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    equivalent_comment_sign_map: HashMap<String, Vec<CommentSign>>,
+    #[serde(default, serialize_with = "serialize_sorted")]
+    copyright_symbol_map: HashMap<String, String>,
+    #[serde(default)]
+    shebang_files: Vec<String>,
+    #[serde(default, serialize_with = "serialize_sorted")]
+    first_line_map: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    blank_line_after_first_line: bool,
+    #[serde(default)]
+    blank_lines_after_copyright: usize,
+    #[serde(default)]
+    collapse_year_runs: bool,
+    #[serde(default = "default_space_after_sign")]
+    space_after_sign: usize,
+    #[serde(
+        default,
+        serialize_with = "serialize_sorted",
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    space_after_sign_map: HashMap<String, usize>,
     ignore_files: Vec<String>,
     ignore_dirs: Vec<String>,
     #[serde(skip)]
@@ -31,40 +79,169 @@ impl Config {
         CFG.set(self).expect("Global config is already assigned to");
     }
 
+    /// Assign the default config with the additions of `TEST_CONFIG`, which
+    /// all tests of whole runs share.
+    #[cfg(test)]
+    pub(crate) fn assign_for_tests() {
+        CFG.get_or_init(|| {
+            let mut value = parse_yaml(DEFAULT_CONFIG).expect("Failed to parse default config");
+            merge_yaml(&mut value, parse_yaml(TEST_CONFIG).unwrap());
+            Self::from_value(value).expect("Failed to load test config")
+        });
+    }
+
+    #[allow(clippy::should_implement_trait)]
     pub fn default() -> Self {
-        let cfg_bytes = include_bytes!("./default_cfg.yml");
-        let cfg_str = String::from_utf8_lossy(cfg_bytes);
-        Self::from_str(&cfg_str).expect("Failed to load default config")
+        Self::from_str(DEFAULT_CONFIG).expect("Failed to load default config")
     }
 
+    /// Load a config file, files listed under `include` are resolved relative to it.
     pub fn from_file(cfg_file: &str) -> Result<Self, CError> {
-        let cfg_str = std::fs::read_to_string(cfg_file)?;
-        Self::from_str(&cfg_str)
+        let value = load_with_includes(Path::new(cfg_file), &mut Vec::new())?;
+        Self::from_value(value)
     }
 
+    /// Parse a config, files listed under `include` are resolved relative to `./`.
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(cfg_str: &str) -> Result<Self, CError> {
-        let mut cfg = serde_yaml::from_str::<Self>(&cfg_str)
+        let value = resolve_ignore_from(parse_yaml(cfg_str)?, Path::new("."))?;
+        let value = resolve_includes(value, Path::new("."), &mut Vec::new())?;
+        Self::from_value(value)
+    }
+
+    fn from_value(value: Value) -> Result<Self, CError> {
+        let value = split_comment_sign_lists(value);
+        validate(&value)?;
+        let mut cfg = serde_yaml::from_value::<Self>(value)
             .map_err(|e| CError::ConfigError(format!("Could not deserialize config: {}", e)))?;
-        cfg.build_glob_pattern();
-        return Ok(cfg);
+        cfg.build_glob_pattern()?;
+        Ok(cfg)
+    }
+
+    /// Serialize the config with includes and `ignore_from` resolved and the
+    /// template in use, which is `template` if given.
+    pub fn to_yaml(&self, template: Option<&str>) -> Result<String, CError> {
+        let mut value = serde_yaml::to_value(self)
+            .map_err(|e| CError::ConfigError(format!("Could not serialize config: {}", e)))?;
+        if let Some(mapping) = value.as_mapping_mut() {
+            mapping.insert(
+                Value::from("template"),
+                Value::from(template.unwrap_or_else(|| self.get_template())),
+            );
+        }
+        serde_yaml::to_string(&value)
+            .map_err(|e| CError::ConfigError(format!("Could not serialize config: {}", e)))
+    }
+
+    /// Get the template of copyright notes, the default one if none is configured.
+    pub fn get_template(&self) -> &str {
+        self.template.as_deref().unwrap_or(DEFAULT_TEMPLATE)
     }
 
     pub fn get_comment_sign(&self, filename: &str) -> Result<&CommentSign, CError> {
-        let filepath = Path::new(filename);
-        let ext_filename = match filepath.extension() {
-            Some(ext) => Some(ext),
-            None => filepath.file_name(),
-        };
+        lookup(&self.comment_sign_map, filename)
+            .ok_or_else(|| CError::UnknownCommentSign(filename.into()))
+    }
 
-        if let Some(ext_filename) = ext_filename {
-            if let Some(ext_filename) = ext_filename.to_str() {
-                if let Some(c_sign) = self.comment_sign_map.get(ext_filename) {
-                    return Ok(c_sign);
-                }
+    /// Get the extensions and file names with a comment sign, sorted.
+    pub fn file_types(&self) -> Vec<&str> {
+        let mut file_types: Vec<&str> = self.comment_sign_map.keys().map(String::as_str).collect();
+        file_types.sort_unstable();
+        file_types
+    }
+
+    /// Get further comment signs of a file whose copyrights are detected and
+    /// rewritten with its comment sign, e.g. `//` for C files with `/* */`.
+    pub fn get_equivalent_comment_signs(&self, filename: &str) -> &[CommentSign] {
+        lookup(&self.equivalent_comment_sign_map, filename).map_or(&[], Vec::as_slice)
+    }
+
+    /// Infer the comment sign of a file of unknown type as the most common
+    /// comment sign of its `siblings`, the first one of them on a tie.
+    ///
+    /// Siblings of unknown or skipped types do not count.
+    pub fn infer_comment_sign<'a>(
+        &self,
+        siblings: impl IntoIterator<Item = &'a str>,
+    ) -> Option<&CommentSign> {
+        let mut counts: Vec<(&CommentSign, usize)> = Vec::new();
+        for comment_sign in siblings
+            .into_iter()
+            .filter_map(|sibling| lookup(&self.comment_sign_map, sibling))
+            .filter(|comment_sign| **comment_sign != CommentSign::Skip)
+        {
+            match counts.iter_mut().find(|(known, _)| *known == comment_sign) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((comment_sign, 1)),
             }
         }
+        // `max_by_key` returns the last maximum, so search from the back
+        counts
+            .into_iter()
+            .rev()
+            .max_by_key(|(_, count)| *count)
+            .map(|(comment_sign, _)| comment_sign)
+    }
+
+    pub fn get_copyright_symbol(&self, filename: &str) -> &str {
+        lookup(&self.copyright_symbol_map, filename)
+            .map(|symbol| symbol.as_str())
+            .unwrap_or(DEFAULT_COPYRIGHT_SYMBOL)
+    }
+
+    /// Check if the first line of a file may be a shebang which has to stay on top.
+    ///
+    /// This is the case for files commented with `#` and files with extensions
+    /// or names listed in `shebang_files`.
+    pub fn is_shebang_capable(&self, filename: &str, comment_sign: &CommentSign) -> bool {
+        if *comment_sign == CommentSign::LeftOnly("#".into()) {
+            return true;
+        }
+
+        lookup_keys(filename)
+            .iter()
+            .any(|key| self.shebang_files.iter().any(|f| f == key))
+    }
+
+    /// Get prefixes of first lines which have to stay above the copyright.
+    ///
+    /// Besides shebangs, these are configured per file type in `first_line_map`,
+    /// e.g. XML declarations or PHP open tags.
+    pub fn get_first_line_prefixes(&self, filename: &str, comment_sign: &CommentSign) -> Vec<&str> {
+        let mut prefixes = Vec::new();
+        if self.is_shebang_capable(filename, comment_sign) {
+            prefixes.push("#!");
+        }
 
-        Err(CError::UnknownCommentSign(filename.into()))
+        if let Some(configured) = lookup(&self.first_line_map, filename) {
+            prefixes.extend(configured.iter().map(|prefix| prefix.as_str()));
+        }
+
+        prefixes
+    }
+
+    /// Whether runs of consecutive years are collapsed into ranges in lists of years.
+    pub fn collapse_year_runs(&self) -> bool {
+        self.collapse_year_runs
+    }
+
+    /// Get the number of spaces between comment signs and copyright notes,
+    /// `space_after_sign` unless configured for a left sign in `space_after_sign_map`.
+    pub fn sign_spacing(&self) -> SignSpacing {
+        SignSpacing {
+            default: self.space_after_sign,
+            per_sign: self.space_after_sign_map.clone(),
+        }
+    }
+
+    /// Get where a new copyright is added to a file.
+    pub fn get_placement(&self, filename: &str, comment_sign: &CommentSign) -> Placement<'_> {
+        Placement {
+            first_line_prefixes: self.get_first_line_prefixes(filename, comment_sign),
+            blank_line_after_first_line: self.blank_line_after_first_line,
+            blank_lines_after_copyright: self.blank_lines_after_copyright,
+            ..Default::default()
+        }
     }
 
     pub fn filter_files<'a>(&self, files: impl Iterator<Item = &'a String>) -> Vec<&'a String> {
@@ -73,41 +250,420 @@ impl Config {
         }
 
         files
-            .filter_map(|filepath| {
-                if let Some(patterns) = self.glob_pattern.as_ref() {
-                    for pattern in patterns {
-                        if pattern.matches(filepath) {
-                            return None;
-                        }
-                    }
+            .filter(|filepath| match self.ignoring_pattern(filepath) {
+                Some(pattern) => {
+                    log::debug!("Ignoring {} because of pattern {}", filepath, pattern);
+                    false
                 }
-
-                Some(filepath)
+                None => true,
             })
             .collect()
     }
 
-    fn build_glob_pattern(&mut self) {
+    /// Get the first pattern of `ignore_files`/`ignore_dirs` matching a file.
+    ///
+    /// Backslashes in the path are treated as separators like `/`, since
+    /// patterns are written with `/` but paths on Windows may use `\`.
+    pub fn ignoring_pattern(&self, filepath: &str) -> Option<&str> {
+        let filepath = filepath.replace('\\', "/");
+        self.glob_pattern
+            .as_ref()?
+            .iter()
+            .find(|pattern| pattern.matches(&filepath))
+            .map(|pattern| pattern.as_str())
+    }
+
+    fn build_glob_pattern(&mut self) -> Result<(), CError> {
         self.glob_pattern = Some(
             self.ignore_files
                 .iter()
                 .chain(self.ignore_dirs.iter())
-                .filter_map(|expr| match Pattern::new(expr) {
-                    Ok(pattern) => Some(pattern),
-                    Err(_) => {
-                        log::error!("Could not compile pattern {}", expr);
-                        None
-                    }
+                .map(|expr| {
+                    Pattern::new(expr).map_err(|e| CError::InvalidGlob {
+                        pattern: expr.clone(),
+                        reason: e.msg.to_owned(),
+                    })
                 })
-                .collect(),
+                .collect::<Result<_, _>>()?,
         );
+        Ok(())
     }
 }
 
+/// Check the fields of a config for errors which deserializing it would
+/// report without naming the field or key.
+fn validate(value: &Value) -> Result<(), CError> {
+    if let Some(field) = REQUIRED_FIELDS
+        .iter()
+        .find(|field| value.get(**field).is_none())
+    {
+        return Err(CError::MissingField(field.to_string()));
+    }
+
+    if let Some(Value::Mapping(comment_signs)) = value.get("comment_sign_map") {
+        for (key, sign) in comment_signs.iter() {
+            if !sign.is_null() && !is_comment_sign(sign) {
+                return Err(CError::InvalidCommentSign(key_name(key)));
+            }
+        }
+    }
+    if let Some(Value::Mapping(comment_signs)) = value.get("equivalent_comment_sign_map") {
+        for (key, signs) in comment_signs.iter() {
+            let valid = match signs {
+                Value::Sequence(signs) => signs.iter().all(is_comment_sign),
+                _ => false,
+            };
+            if !valid {
+                return Err(CError::InvalidCommentSign(key_name(key)));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Move all but the first of lists of comment signs in `comment_sign_map` to
+/// `equivalent_comment_sign_map`, e.g. `c: [["/*", "*/"], "//"]`.
+///
+/// Exactly two strings are a pair of enclosing comment signs as before, any
+/// other list is one of comment signs. Invalid signs are left to `validate`.
+fn split_comment_sign_lists(mut value: Value) -> Value {
+    let mut equivalents = Mapping::new();
+    if let Some(Value::Mapping(comment_signs)) = value.get_mut("comment_sign_map") {
+        for (key, signs) in comment_signs.iter_mut() {
+            if is_comment_sign(signs) {
+                continue;
+            }
+            if let Value::Sequence(list) = signs {
+                if list.is_empty() {
+                    continue;
+                }
+                let canonical = list.remove(0);
+                equivalents.insert(key.clone(), std::mem::replace(signs, canonical));
+            }
+        }
+    }
+    if !equivalents.is_empty() {
+        merge_yaml(
+            &mut value,
+            Value::Mapping(Mapping::from_iter([(
+                Value::from("equivalent_comment_sign_map"),
+                Value::Mapping(equivalents),
+            )])),
+        );
+    }
+    value
+}
+
+/// Check if a value is a leading comment sign or a pair of enclosing ones.
+fn is_comment_sign(value: &Value) -> bool {
+    match value {
+        Value::String(_) => true,
+        Value::Sequence(signs) => signs.len() == 2 && signs.iter().all(Value::is_string),
+        _ => false,
+    }
+}
+
+fn key_name(key: &Value) -> String {
+    match key.as_str() {
+        Some(key) => key.to_owned(),
+        None => format!("{:?}", key),
+    }
+}
+
+fn parse_yaml(cfg_str: &str) -> Result<Value, CError> {
+    for warning in comment_sign_warnings(cfg_str) {
+        log::warn!("{}", warning);
+    }
+    serde_yaml::from_str(cfg_str)
+        .map_err(|e| CError::ConfigError(format!("Could not deserialize config: {}", e)))
+}
+
+/// Entries of a mapping in the order of the file, including duplicate keys
+/// which are otherwise silently overwritten by the last one.
+#[derive(Default)]
+struct MapEntries(Vec<(String, Value)>);
+
+impl<'de> Deserialize<'de> for MapEntries {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EntriesVisitor;
+
+        impl<'de> Visitor<'de> for EntriesVisitor {
+            type Value = MapEntries;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a mapping")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<MapEntries, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(MapEntries(entries))
+            }
+        }
+
+        deserializer.deserialize_map(EntriesVisitor)
+    }
+}
+
+#[derive(Deserialize)]
+struct RawCommentSigns {
+    #[serde(default)]
+    comment_sign_map: MapEntries,
+}
+
+/// Find comment signs in a config which are given twice, are empty or look
+/// like two leading comment signs given as a pair of enclosing ones.
+///
+/// Invalid configs give no warnings, they are reported when parsing them.
+fn comment_sign_warnings(cfg_str: &str) -> Vec<String> {
+    let entries = match serde_yaml::from_str::<RawCommentSigns>(cfg_str) {
+        Ok(raw) => raw.comment_sign_map.0,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut seen = HashSet::new();
+    let mut warnings = Vec::new();
+    for (key, value) in entries.iter() {
+        if !seen.insert(key) {
+            warnings.push(format!(
+                "Comment sign for {} is given more than once, the last one is used",
+                key
+            ));
+        }
+        let empty = match value {
+            Value::String(sign) => sign.trim().is_empty(),
+            Value::Sequence(signs) => signs
+                .iter()
+                .any(|sign| sign.as_str().is_some_and(|sign| sign.trim().is_empty())),
+            _ => false,
+        };
+        if empty {
+            warnings.push(format!("Comment sign for {} is empty", key));
+        }
+        if let Some((left, right)) = as_line_comment_pair(value) {
+            warnings.push(format!(
+                "Comment signs {:?} and {:?} for {} enclose the copyright, list other \
+                 leading comment signs under equivalent_comment_sign_map instead",
+                left, right, key
+            ));
+        }
+    }
+    warnings
+}
+
+/// Leading comment signs which hardly ever close a comment.
+const LINE_COMMENT_SIGNS: [&str; 5] = ["#", "//", "--", ";", "%"];
+
+/// Get a pair of enclosing comment signs whose right one is a leading comment
+/// sign, e.g. `py: ["#", "//"]` meant as two leading comment signs.
+fn as_line_comment_pair(value: &Value) -> Option<(&str, &str)> {
+    match value.as_sequence()?.as_slice() {
+        [Value::String(left), Value::String(right)]
+            if LINE_COMMENT_SIGNS.contains(&right.trim()) =>
+        {
+            Some((left, right))
+        }
+        _ => None,
+    }
+}
+
+/// Load a config file and the files it includes. `stack` holds the files
+/// currently being loaded to detect include cycles.
+fn load_with_includes(cfg_file: &Path, stack: &mut Vec<PathBuf>) -> Result<Value, CError> {
+    let canonical = cfg_file.canonicalize()?;
+    if stack.contains(&canonical) {
+        return Err(CError::ConfigError(format!(
+            "Config {} includes itself",
+            cfg_file.display()
+        )));
+    }
+    stack.push(canonical);
+
+    let value = parse_yaml(&std::fs::read_to_string(cfg_file)?)?;
+    let base_dir = cfg_file.parent().unwrap_or_else(|| Path::new("."));
+    let value = resolve_ignore_from(value, base_dir)?;
+    let value = resolve_includes(value, base_dir, stack)?;
+
+    stack.pop();
+    Ok(value)
+}
+
+/// Merge the files listed under `include` into a config, values of the
+/// including config take precedence.
+fn resolve_includes(
+    mut value: Value,
+    base_dir: &Path,
+    stack: &mut Vec<PathBuf>,
+) -> Result<Value, CError> {
+    let includes = match value.as_mapping_mut() {
+        Some(mapping) => mapping.remove(&Value::from("include")),
+        None => None,
+    };
+    let includes: Vec<String> = match includes {
+        Some(includes) => serde_yaml::from_value(includes)
+            .map_err(|e| CError::ConfigError(format!("Invalid include: {}", e)))?,
+        None => return Ok(value),
+    };
+
+    let mut merged = Value::Mapping(Mapping::new());
+    for include in includes {
+        merge_yaml(
+            &mut merged,
+            load_with_includes(&base_dir.join(include), stack)?,
+        );
+    }
+    merge_yaml(&mut merged, value);
+    Ok(merged)
+}
+
+/// Add the patterns of the gitignore-style file given as `ignore_from` to
+/// `ignore_files`, the path is relative to `base_dir`.
+fn resolve_ignore_from(mut value: Value, base_dir: &Path) -> Result<Value, CError> {
+    let mapping = match value.as_mapping_mut() {
+        Some(mapping) => mapping,
+        None => return Ok(value),
+    };
+    let ignore_from: String = match mapping.remove(&Value::from("ignore_from")) {
+        Some(ignore_from) => serde_yaml::from_value(ignore_from)
+            .map_err(|e| CError::ConfigError(format!("Invalid ignore_from: {}", e)))?,
+        None => return Ok(value),
+    };
+
+    let ignore_file = base_dir.join(ignore_from);
+    let patterns = std::fs::read_to_string(&ignore_file)
+        .map_err(|_| CError::ReadError(ignore_file.display().to_string()))?
+        .lines()
+        .flat_map(gitignore_to_globs)
+        .map(Value::from)
+        .collect();
+    merge_yaml(
+        &mut value,
+        Value::Mapping(Mapping::from_iter([(
+            Value::from("ignore_files"),
+            Value::Sequence(patterns),
+        )])),
+    );
+    Ok(value)
+}
+
+/// Translate a line of a gitignore-style file to glob patterns.
+///
+/// Blank lines and comments starting with `#` are skipped. As in gitignore,
+/// patterns without a `/` (except a trailing one) match at any depth and
+/// patterns also match everything within matching directories. Negated
+/// patterns starting with `!` are not supported and skipped.
+fn gitignore_to_globs(line: &str) -> Vec<String> {
+    let line = line.trim_end();
+    if line.is_empty() || line.starts_with('#') {
+        return Vec::new();
+    }
+    if line.starts_with('!') {
+        log::warn!("Skipping unsupported negated ignore pattern {}", line);
+        return Vec::new();
+    }
+    let line = line.strip_prefix('\\').unwrap_or(line);
+
+    let (pattern, dir_only) = match line.strip_suffix('/') {
+        Some(pattern) => (pattern, true),
+        None => (line, false),
+    };
+    let pattern = match pattern.contains('/') {
+        true => pattern.trim_start_matches('/').to_owned(),
+        false => format!("**/{}", pattern),
+    };
+    match dir_only {
+        true => vec![format!("{}/*", pattern)],
+        false => vec![pattern.clone(), format!("{}/*", pattern)],
+    }
+}
+
+/// Number of spaces after comment signs if `space_after_sign` is not configured.
+fn default_space_after_sign() -> usize {
+    SignSpacing::default().default
+}
+
+/// Serialize a map sorted by keys for a stable output.
+fn serialize_sorted<S: Serializer, V: Serialize>(
+    map: &HashMap<String, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+/// Merge mappings key by key and concatenate sequences, other values of
+/// `other` replace those of `base`.
+fn merge_yaml(base: &mut Value, other: Value) {
+    match (base, other) {
+        (Value::Mapping(base), Value::Mapping(other)) => {
+            for (key, value) in other {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge_yaml(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Sequence(base), Value::Sequence(other)) => base.extend(other),
+        (base, other) => *base = other,
+    }
+}
+
+/// Get the keys under which a file is looked up in the maps of the config, in
+/// order of precedence.
+///
+/// Files are looked up by their extension or by their name if they have none,
+/// e.g. `Dockerfile`. Compound extensions take precedence over simple ones, so
+/// `archive.tar.gz` is looked up as `tar.gz` before `gz`. Dotfiles like
+/// `.gitignore` or `.env.local` are looked up by their full name first, so
+/// `.gitignore` does not collide with an extension `gitignore`.
+fn lookup_keys(filename: &str) -> Vec<&str> {
+    let name = match Path::new(filename)
+        .file_name()
+        .and_then(|name| name.to_str())
+    {
+        Some(name) => name,
+        None => return Vec::new(),
+    };
+
+    let mut keys = Vec::new();
+    if name.starts_with('.') {
+        keys.push(name);
+    }
+    keys.extend(
+        name.char_indices()
+            .skip(1)
+            .filter(|(_, char)| *char == '.')
+            .map(|(idx, _)| &name[idx + 1..])
+            .filter(|ext| !ext.is_empty()),
+    );
+    if keys.is_empty() {
+        keys.push(name);
+    }
+    keys
+}
+
+/// Get the most specific key a file is looked up under, e.g. `tar.gz` for
+/// `archive.tar.gz`, to name its type.
+pub fn file_type(filename: &str) -> Option<&str> {
+    lookup_keys(filename).first().copied()
+}
+
+/// Look up the value for a file in a map of the config, see `lookup_keys`.
+fn lookup<'a, V>(map: &'a HashMap<String, V>, filename: &str) -> Option<&'a V> {
+    lookup_keys(filename)
+        .into_iter()
+        .find_map(|key| map.get(key))
+}
+
 #[cfg(test)]
 mod test {
 
-    use super::{CommentSign, Config};
+    use super::{comment_sign_warnings, gitignore_to_globs, CError, CommentSign, Config};
+    use crate::regex_ops::{CopyrightCache, Leniency};
+    use crate::template::{Template, DEFAULT_TEMPLATE};
 
     #[test]
     fn test_config_from_file() {
@@ -124,13 +680,515 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_copyright_symbol_map() {
+        let cfg = Config::from_str(
+            r#"
+comment_sign_map:
+  rs: "//"
+  md: ["<!--", "-->"]
+copyright_symbol_map:
+  md: "©"
+ignore_files: []
+ignore_dirs: []
+"#,
+        )
+        .unwrap();
+        assert_eq!(cfg.get_copyright_symbol("src/main.rs"), "(C)");
+        assert_eq!(cfg.get_copyright_symbol("docs/README.md"), "©");
+    }
+
+    #[test]
+    fn test_shebang_capable() {
+        let cfg = Config::from_str(
+            r##"
+comment_sign_map:
+  rs: "//"
+  sh: "#"
+  js: "//"
+shebang_files: ["js"]
+ignore_files: []
+ignore_dirs: []
+"##,
+        )
+        .unwrap();
+        assert!(cfg.is_shebang_capable("run.sh", cfg.get_comment_sign("run.sh").unwrap()));
+        assert!(cfg.is_shebang_capable("cli.js", cfg.get_comment_sign("cli.js").unwrap()));
+        assert!(!cfg.is_shebang_capable("lib.rs", cfg.get_comment_sign("lib.rs").unwrap()));
+    }
+
+    #[test]
+    fn test_first_line_prefixes() {
+        let cfg = Config::default();
+        let prefixes = |filename| {
+            cfg.get_first_line_prefixes(filename, cfg.get_comment_sign(filename).unwrap())
+        };
+        assert_eq!(prefixes("run.sh"), vec!["#!"]);
+        assert_eq!(prefixes("pom.xml"), vec!["<?xml"]);
+        assert_eq!(prefixes("index.php"), vec!["#!", "<?php"]);
+        assert!(prefixes("lib.rs").is_empty());
+    }
+
+    #[test]
+    fn test_dotfiles() {
+        let cfg = Config::from_str(
+            r##"
+comment_sign_map:
+  .gitignore: "#"
+  gitignore: "//"
+  .env: "#"
+  json: "//"
+ignore_files: []
+ignore_dirs: []
+"##,
+        )
+        .unwrap();
+        let sign = |filename| cfg.get_comment_sign(filename).ok();
+
+        assert_eq!(sign(".gitignore"), Some(&CommentSign::LeftOnly("#".into())));
+        assert_eq!(
+            sign("sub/.gitignore"),
+            Some(&CommentSign::LeftOnly("#".into()))
+        );
+        assert_eq!(
+            sign("file.gitignore"),
+            Some(&CommentSign::LeftOnly("//".into()))
+        );
+        assert_eq!(sign(".env"), Some(&CommentSign::LeftOnly("#".into())));
+        // Dotfiles with an extension fall back to it
+        assert_eq!(
+            sign(".eslintrc.json"),
+            Some(&CommentSign::LeftOnly("//".into()))
+        );
+        assert_eq!(sign(".env.local"), None);
+        assert_eq!(sign("env"), None);
+    }
+
+    #[test]
+    fn test_compound_extensions() {
+        let cfg = Config::from_str(
+            r##"
+comment_sign_map:
+  tar.gz: "#"
+  gz: "//"
+  ts: "//"
+ignore_files: []
+ignore_dirs: []
+"##,
+        )
+        .unwrap();
+        let sign = |filename| cfg.get_comment_sign(filename).ok();
+
+        assert_eq!(
+            sign("archive.tar.gz"),
+            Some(&CommentSign::LeftOnly("#".into()))
+        );
+        assert_eq!(
+            sign("backup.archive.tar.gz"),
+            Some(&CommentSign::LeftOnly("#".into()))
+        );
+        assert_eq!(sign("file.gz"), Some(&CommentSign::LeftOnly("//".into())));
+        assert_eq!(
+            sign("file.zip.gz"),
+            Some(&CommentSign::LeftOnly("//".into()))
+        );
+        assert_eq!(
+            sign("schema.test.ts"),
+            Some(&CommentSign::LeftOnly("//".into()))
+        );
+    }
+
+    #[test]
+    fn test_ignoring_pattern() {
+        let cfg = Config::default();
+        assert_eq!(cfg.ignoring_pattern("notes.txt"), Some("*.txt"));
+        assert_eq!(
+            cfg.ignoring_pattern("dev/__pycache__/file.py"),
+            Some("*__pycache__*")
+        );
+        assert_eq!(cfg.ignoring_pattern("main.rs"), None);
+    }
+
+    #[test]
+    fn test_ignoring_pattern_backslash_path() {
+        let cfg = Config::default();
+        assert_eq!(
+            cfg.ignoring_pattern(r"web\node_modules"),
+            Some("**/node_modules")
+        );
+        let files = [
+            String::from(r"web\node_modules"),
+            String::from(r"src\main.rs"),
+        ];
+        assert_eq!(cfg.filter_files(files.iter()), vec![&files[1]]);
+    }
+
+    #[test]
+    fn test_include() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("lang")).unwrap();
+        std::fs::write(
+            dir.path().join("base.yml"),
+            r##"
+include:
+  - lang/rust.yml
+comment_sign_map:
+  py: "#"
+  rs: "/*"
+ignore_files: ["*.txt"]
+ignore_dirs: []
+"##,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("lang/rust.yml"),
+            r##"
+comment_sign_map:
+  rs: "//"
+  toml: "#"
+ignore_files: ["Cargo.lock"]
+"##,
+        )
+        .unwrap();
+
+        let cfg = Config::from_file(dir.path().join("base.yml").to_str().unwrap()).unwrap();
+        assert_eq!(
+            cfg.get_comment_sign("a.py").unwrap(),
+            &CommentSign::LeftOnly("#".into())
+        );
+        assert_eq!(
+            cfg.get_comment_sign("Cargo.toml").unwrap(),
+            &CommentSign::LeftOnly("#".into())
+        );
+        // The including config wins
+        assert_eq!(
+            cfg.get_comment_sign("main.rs").unwrap(),
+            &CommentSign::LeftOnly("/*".into())
+        );
+        let files = [
+            "Cargo.lock".to_owned(),
+            "a.txt".to_owned(),
+            "a.py".to_owned(),
+        ];
+        assert_eq!(cfg.filter_files(files.iter()), vec!["a.py"]);
+    }
+
+    #[test]
+    fn test_infer_comment_sign() {
+        let cfg = Config::default();
+        assert_eq!(
+            cfg.infer_comment_sign(["a.py", "b.rs", "c.py", "d.unknown"]),
+            Some(&CommentSign::LeftOnly("#".into()))
+        );
+        assert_eq!(
+            cfg.infer_comment_sign(["b.rs", "a.py"]),
+            Some(&CommentSign::LeftOnly("//".into()))
+        );
+        assert_eq!(cfg.infer_comment_sign(["d.unknown"]), None);
+    }
+
+    #[test]
+    fn test_equivalent_comment_signs() {
+        let cfg = Config::from_str(
+            r##"
+comment_sign_map:
+  c: ["/*", "*/"]
+  h: ["/*", "*/"]
+equivalent_comment_sign_map:
+  c: ["//", ["/**", "*/"]]
+ignore_files: []
+ignore_dirs: []
+"##,
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.get_equivalent_comment_signs("main.c"),
+            &[
+                CommentSign::LeftOnly("//".into()),
+                CommentSign::Enclosing("/**".into(), "*/".into())
+            ]
+        );
+        assert!(cfg.get_equivalent_comment_signs("main.h").is_empty());
+        assert!(cfg
+            .to_yaml(None)
+            .unwrap()
+            .contains("equivalent_comment_sign_map"));
+        assert!(!Config::default()
+            .to_yaml(None)
+            .unwrap()
+            .contains("equivalent_comment_sign_map"));
+
+        let res = Config::from_str(
+            "comment_sign_map: {}\nequivalent_comment_sign_map:\n  c: \"//\"\nignore_files: []\nignore_dirs: []\n",
+        );
+        assert!(matches!(res, Err(CError::InvalidCommentSign(ext)) if ext == "c"));
+    }
+
+    #[test]
+    fn test_comment_sign_lists() {
+        let cfg = Config::from_str(
+            r##"
+comment_sign_map:
+  c: [["/*", "*/"], "//"]
+  ini: [";", "#", "//"]
+  css: ["/*", "*/"]
+  sh: ["#"]
+equivalent_comment_sign_map:
+  c: [["/**", "*/"]]
+ignore_files: []
+ignore_dirs: []
+"##,
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.get_comment_sign("main.c").unwrap(),
+            &CommentSign::Enclosing("/*".into(), "*/".into())
+        );
+        assert_eq!(
+            cfg.get_equivalent_comment_signs("main.c"),
+            &[
+                CommentSign::Enclosing("/**".into(), "*/".into()),
+                CommentSign::LeftOnly("//".into()),
+            ]
+        );
+        assert_eq!(
+            cfg.get_comment_sign("a.ini").unwrap(),
+            &CommentSign::LeftOnly(";".into())
+        );
+        assert_eq!(cfg.get_equivalent_comment_signs("a.ini").len(), 2);
+        assert_eq!(
+            cfg.get_comment_sign("a.css").unwrap(),
+            &CommentSign::Enclosing("/*".into(), "*/".into())
+        );
+        assert!(cfg.get_equivalent_comment_signs("a.css").is_empty());
+        assert_eq!(
+            cfg.get_comment_sign("run.sh").unwrap(),
+            &CommentSign::LeftOnly("#".into())
+        );
+
+        // Copyrights with any of the comment signs are detected
+        let template = Template::parse(DEFAULT_TEMPLATE).unwrap();
+        let cache = CopyrightCache::new("Org", Leniency::default());
+        let regexes: Vec<_> = std::iter::once(cfg.get_comment_sign("main.c").unwrap())
+            .chain(cfg.get_equivalent_comment_signs("main.c"))
+            .map(|comment_sign| cache.get_regex(&template, comment_sign, "(c)").unwrap())
+            .collect();
+        for line in [
+            "/* Copyright (c) Org 2020 */",
+            "/** Copyright (c) Org 2020 */",
+            "// Copyright (c) Org 2020",
+        ] {
+            assert_eq!(
+                regexes.iter().filter(|regex| regex.is_match(line)).count(),
+                1,
+                "{}",
+                line
+            );
+        }
+
+        let res = Config::from_str(
+            "comment_sign_map:\n  c: [\"//\", 3]\nignore_files: []\nignore_dirs: []\n",
+        );
+        assert!(matches!(res, Err(CError::InvalidCommentSign(ext)) if ext == "c"));
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.yml"), "include: [b.yml]\n").unwrap();
+        std::fs::write(dir.path().join("b.yml"), "include: [a.yml]\n").unwrap();
+
+        let res = Config::from_file(dir.path().join("a.yml").to_str().unwrap());
+        assert!(matches!(res, Err(CError::ConfigError(_))));
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let res = Config::from_str("comment_sign_map:\n  py: \"#\"\nignore_files: []\n");
+        assert!(matches!(res, Err(CError::MissingField(field)) if field == "ignore_dirs"));
+
+        let res = Config::from_str(
+            "comment_sign_map:\n  py: \"#\"\nignore_files: [\"[a-\"]\nignore_dirs: []\n",
+        );
+        assert!(matches!(res, Err(CError::InvalidGlob { pattern, .. }) if pattern == "[a-"));
+
+        let res =
+            Config::from_str("comment_sign_map:\n  css: []\nignore_files: []\nignore_dirs: []\n");
+        assert!(matches!(res, Err(CError::InvalidCommentSign(ext)) if ext == "css"));
+
+        let res =
+            Config::from_str("comment_sign_map:\n  py: 3\nignore_files: []\nignore_dirs: []\n");
+        assert!(matches!(res, Err(CError::InvalidCommentSign(ext)) if ext == "py"));
+
+        let res = Config::from_str("comment_sign_map: {}\nignore_files: 3\nignore_dirs: []\n");
+        assert!(matches!(res, Err(CError::ConfigError(_))));
+    }
+
+    #[test]
+    fn test_comment_sign_warnings() {
+        let warnings = comment_sign_warnings(
+            r##"
+comment_sign_map:
+  py: "#"
+  rs: "//"
+  css: ["/*", ""]
+  py: "//"
+  sh: ""
+  rb: ["#", "//"]
+  sql: ["/*", "*/"]
+  c: [["/*", "*/"], "//"]
+ignore_files: []
+"##,
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                "Comment sign for css is empty",
+                "Comment sign for py is given more than once, the last one is used",
+                "Comment sign for sh is empty",
+                "Comment signs \"#\" and \"//\" for rb enclose the copyright, list other \
+                 leading comment signs under equivalent_comment_sign_map instead",
+            ]
+        );
+        assert!(comment_sign_warnings(super::DEFAULT_CONFIG).is_empty());
+        assert!(comment_sign_warnings("comment_sign_map: [").is_empty());
+    }
+
+    #[test]
+    fn test_gitignore_to_globs() {
+        assert!(gitignore_to_globs("").is_empty());
+        assert!(gitignore_to_globs("# generated files").is_empty());
+        assert!(gitignore_to_globs("!keep.txt").is_empty());
+        assert_eq!(
+            gitignore_to_globs("*.log  "),
+            vec!["**/*.log", "**/*.log/*"]
+        );
+        assert_eq!(
+            gitignore_to_globs("\\#file"),
+            vec!["**/#file", "**/#file/*"]
+        );
+        assert_eq!(gitignore_to_globs("vendor/"), vec!["**/vendor/*"]);
+        assert_eq!(
+            gitignore_to_globs("/docs/api"),
+            vec!["docs/api", "docs/api/*"]
+        );
+    }
+
+    #[test]
+    fn test_ignore_from() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("cfg")).unwrap();
+        std::fs::write(
+            dir.path().join("cfg/base.yml"),
+            r##"
+ignore_from: ../.copyrightignore
+comment_sign_map:
+  py: "#"
+ignore_files: ["*.txt"]
+ignore_dirs: []
+"##,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join(".copyrightignore"),
+            "# Generated code\n\ngenerated/\n/setup.py\n*_pb2.py\n",
+        )
+        .unwrap();
+
+        let cfg = Config::from_file(dir.path().join("cfg/base.yml").to_str().unwrap()).unwrap();
+        let files = [
+            "a.txt".to_owned(),
+            "src/generated/a.py".to_owned(),
+            "src/api_pb2.py".to_owned(),
+            "setup.py".to_owned(),
+            "src/setup.py".to_owned(),
+            "src/main.py".to_owned(),
+        ];
+        assert_eq!(
+            cfg.filter_files(files.iter()),
+            vec!["src/setup.py", "src/main.py"]
+        );
+        assert_eq!(cfg.ignoring_pattern("src/api_pb2.py"), Some("**/*_pb2.py"));
+
+        std::fs::write(dir.path().join(".copyrightignore"), "/gen[/\n").unwrap();
+        let err = Config::from_file(dir.path().join("cfg/base.yml").to_str().unwrap()).unwrap_err();
+        assert!(matches!(&err, CError::InvalidGlob { pattern, .. } if pattern == "gen[/*"));
+        assert!(err.to_string().contains("gen["));
+    }
+
+    #[test]
+    fn test_to_yaml() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("base.yml"),
+            r##"
+include: [rust.yml]
+comment_sign_map:
+  py: "#"
+  css: ["/*", "*/"]
+  md: ~
+ignore_files: ["*.txt"]
+ignore_dirs: []
+"##,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("rust.yml"),
+            "comment_sign_map:\n  rs: \"//\"\nignore_files: [Cargo.lock]\n",
+        )
+        .unwrap();
+
+        let cfg = Config::from_file(dir.path().join("base.yml").to_str().unwrap()).unwrap();
+        let yaml = cfg.to_yaml(None).unwrap();
+        assert!(yaml.contains("template: \"Copyright (c) {name} {years}\""));
+        assert!(yaml.contains("  rs: //\n"));
+        assert!(yaml.contains("  - Cargo.lock\n  - \"*.txt\"\n"));
+        assert!(!yaml.contains("include"));
+        assert!(!yaml.contains("glob_pattern"));
+
+        let reloaded = Config::from_str(&cfg.to_yaml(Some("{name} {years}")).unwrap()).unwrap();
+        assert_eq!(reloaded.get_template(), "{name} {years}");
+        assert_eq!(
+            reloaded.get_comment_sign("a.css").unwrap(),
+            &CommentSign::Enclosing("/*".into(), "*/".into())
+        );
+        assert_eq!(
+            reloaded.get_comment_sign("a.md").unwrap(),
+            &CommentSign::Skip
+        );
+    }
+
+    #[test]
+    fn test_placement() {
+        let cfg = Config::from_str(
+            r##"
+comment_sign_map:
+  sh: "#"
+blank_line_after_first_line: true
+blank_lines_after_copyright: 2
+ignore_files: []
+ignore_dirs: []
+"##,
+        )
+        .unwrap();
+        let placement = cfg.get_placement("run.sh", &CommentSign::LeftOnly("#".into()));
+        assert_eq!(placement.first_line_prefixes, vec!["#!"]);
+        assert!(placement.blank_line_after_first_line);
+        assert_eq!(placement.blank_lines_after_copyright, 2);
+
+        let default_cfg = Config::default();
+        let placement = default_cfg.get_placement("run.sh", &CommentSign::LeftOnly("#".into()));
+        assert!(!placement.blank_line_after_first_line);
+        assert_eq!(placement.blank_lines_after_copyright, 0);
+    }
+
     #[test]
     fn test_filter_files() {
-        let unfiltered: Vec<String> = vec!["dev/myfile.rs", "general/myfile.py", "another_file.py"]
+        let unfiltered: Vec<String> = ["dev/myfile.rs", "general/myfile.py", "another_file.py"]
             .iter()
             .map(|&elm| elm.into())
             .collect();
-        let to_filter: Vec<String> = vec![
+        let to_filter: Vec<String> = [
             "filter_me.txt",
             "./dev/I_want_out.txt",
             "dev/__pycache__/valid_file_in_ignored_folder.py",
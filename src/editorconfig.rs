@@ -0,0 +1,182 @@
+//! Read settings of files from `.editorconfig` files.
+//!
+//! Only the subset needed for added lines is supported: sections with glob
+//! patterns including `{a,b}` alternatives, `root = true` and `end_of_line`.
+//! Files closer to a file take precedence, as do later sections within a file.
+
+use crate::file_ops::LineEnding;
+use crate::CError;
+use glob::{MatchOptions, Pattern};
+use std::path::Path;
+
+const EDITORCONFIG: &str = ".editorconfig";
+
+/// Get the `end_of_line` setting for `filepath` relative to `repo_path` from
+/// the `.editorconfig` files of its directory and its parents within the
+/// repository, `None` if there is none or it is neither `lf` nor `crlf`.
+pub fn end_of_line(repo_path: &Path, filepath: &str) -> Result<Option<LineEnding>, CError> {
+    let mut editorconfigs = Vec::new();
+    let mut dir = Path::new(filepath).parent();
+    while let Some(rel_dir) = dir {
+        let path = repo_path.join(rel_dir).join(EDITORCONFIG);
+        if path.is_file() {
+            let content = std::fs::read_to_string(&path)
+                .map_err(|_| CError::ReadError(path.display().to_string()))?;
+            let (root, sections) = parse(&content);
+            editorconfigs.push((rel_dir, sections));
+            if root {
+                break;
+            }
+        }
+        dir = rel_dir.parent();
+    }
+
+    let mut end_of_line = None;
+    for (rel_dir, sections) in editorconfigs.into_iter().rev() {
+        // Paths of files are relative to the repository with `/`
+        let rel_path = Path::new(filepath)
+            .strip_prefix(rel_dir)
+            .unwrap_or(Path::new(filepath))
+            .to_string_lossy()
+            .replace('\\', "/");
+        for section in sections.iter().filter(|s| s.matches(&rel_path)) {
+            if let Some(value) = section.end_of_line.as_deref() {
+                end_of_line = match value {
+                    "lf" => Some(LineEnding::Lf),
+                    "crlf" => Some(LineEnding::Crlf),
+                    _ => None,
+                };
+            }
+        }
+    }
+    Ok(end_of_line)
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Section {
+    patterns: Vec<String>,
+    end_of_line: Option<String>,
+}
+
+impl Section {
+    /// Check if the section applies to a file at `rel_path` relative to the
+    /// directory of the `.editorconfig`. Patterns without `/` apply to files
+    /// of that name in any subdirectory.
+    fn matches(&self, rel_path: &str) -> bool {
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        let filename = rel_path.rsplit('/').next().unwrap_or(rel_path);
+        self.patterns.iter().any(|pattern| {
+            let (pattern, path) = match pattern.contains('/') {
+                true => (pattern.trim_start_matches('/'), rel_path),
+                false => (pattern.as_str(), filename),
+            };
+            Pattern::new(pattern).is_ok_and(|pattern| pattern.matches_with(path, options))
+        })
+    }
+}
+
+/// Parse whether the file is marked as root and its sections.
+fn parse(content: &str) -> (bool, Vec<Section>) {
+    let mut root = false;
+    let mut sections: Vec<Section> = Vec::new();
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            sections.push(Section {
+                patterns: expand_braces(header),
+                end_of_line: None,
+            });
+            continue;
+        }
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim().to_lowercase(), value.trim().to_lowercase()),
+            None => continue,
+        };
+        match (sections.last_mut(), key.as_str()) {
+            (None, "root") => root = value == "true",
+            (Some(section), "end_of_line") => section.end_of_line = Some(value),
+            _ => (),
+        }
+    }
+    (root, sections)
+}
+
+/// Expand alternatives like `*.{js,ts}` into one pattern each.
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let (start, end) = match (pattern.find('{'), pattern.find('}')) {
+        (Some(start), Some(end)) if start < end => (start, end),
+        _ => return vec![pattern.to_owned()],
+    };
+    pattern[start + 1..end]
+        .split(',')
+        .flat_map(|alternative| {
+            expand_braces(&[&pattern[..start], alternative, &pattern[end + 1..]].concat())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{end_of_line, expand_braces, parse, Section};
+    use crate::file_ops::LineEnding;
+    use crate::test_utils::write_file;
+
+    #[test]
+    fn test_parse() {
+        let content = "root = true\n\n[*]\nend_of_line = lf\n\n\
+                       # Windows scripts\n[*.{bat,cmd}]\nend_of_line = CRLF\nindent_size = 4\n";
+        assert_eq!(
+            parse(content),
+            (
+                true,
+                vec![
+                    Section {
+                        patterns: vec!["*".into()],
+                        end_of_line: Some("lf".into()),
+                    },
+                    Section {
+                        patterns: vec!["*.bat".into(), "*.cmd".into()],
+                        end_of_line: Some("crlf".into()),
+                    },
+                ]
+            )
+        );
+        assert_eq!(
+            expand_braces("{src,lib}/*.{c,h}"),
+            ["src/*.c", "src/*.h", "lib/*.c", "lib/*.h"]
+        );
+    }
+
+    #[test]
+    fn test_end_of_line() {
+        let repo = tempfile::tempdir().unwrap();
+        write_file(
+            repo.path(),
+            ".editorconfig",
+            "root = true\n[*]\nend_of_line = lf\n[*.bat]\nend_of_line = crlf\n\
+             [scripts/*.ps1]\nend_of_line = crlf\n",
+        );
+        write_file(
+            repo.path(),
+            "legacy/.editorconfig",
+            "[*.py]\nend_of_line = crlf\n",
+        );
+
+        let eol = |filepath| end_of_line(repo.path(), filepath).unwrap();
+        assert_eq!(eol("main.py"), Some(LineEnding::Lf));
+        assert_eq!(eol("tools/run.bat"), Some(LineEnding::Crlf));
+        assert_eq!(eol("scripts/run.ps1"), Some(LineEnding::Crlf));
+        assert_eq!(eol("scripts/nested/run.ps1"), Some(LineEnding::Lf));
+        assert_eq!(eol("legacy/old.py"), Some(LineEnding::Crlf));
+        assert_eq!(eol("legacy/old.rs"), Some(LineEnding::Lf));
+
+        let repo = tempfile::tempdir().unwrap();
+        assert_eq!(end_of_line(repo.path(), "main.py").unwrap(), None);
+    }
+}
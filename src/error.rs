@@ -6,24 +6,55 @@ pub enum CError {
     #[error("No comment sign found for file {0}")]
     UnknownCommentSign(String),
 
-    #[error("Error while running git subcommand: {0}")]
-    GitCmdError(String),
+    #[error("git {command} exited {}: {stderr}", describe_exit_code(.code))]
+    GitCmdError {
+        command: &'static str,
+        code: Option<i32>,
+        stderr: String,
+    },
 
-    #[error("Invalid configuration")]
+    #[error("Invalid configuration: {0}")]
     ConfigError(String),
 
+    #[error("Invalid configuration: missing field {0}")]
+    MissingField(String),
+
+    #[error("Invalid configuration: glob pattern {pattern} cannot be compiled: {reason}")]
+    InvalidGlob { pattern: String, reason: String },
+
+    #[error("Invalid configuration: comment sign for {0} must be ~, a sign, a pair of signs or a list of them")]
+    InvalidCommentSign(String),
+
+    #[error("No comment sign configured for file types {}", .0.join(", "))]
+    UncoveredFileTypes(Vec<String>),
+
+    #[error("Invalid copyright template: {0}")]
+    TemplateError(String),
+
     #[error("Could not read {0}")]
     ReadError(String),
 
     #[error("Could not write {0}")]
     WriteError(String),
 
+    #[error("Processing {0} panicked: {1}")]
+    Panicked(String, String),
+
+    #[error("Post-write command failed for {file}: {reason}")]
+    PostWriteCmdError { file: String, reason: String },
+
     #[error("Some copyrights could not be fixed, please check the output")]
     FixError,
 
     #[error("The copyright job changed tracked files that should be committed")]
     FilesChanged,
 
+    #[error("{0} files need a copyright update")]
+    CopyrightsOutdated(usize),
+
+    #[error("The template changed since the last run, pass --confirm-reformat to rewrite all copyrights")]
+    TemplateChanged,
+
     #[error(transparent)]
     GenericIOError(#[from] std::io::Error),
 
@@ -33,3 +64,10 @@ pub enum CError {
     #[error(transparent)]
     RegexError(#[from] regex::Error),
 }
+
+fn describe_exit_code(code: &Option<i32>) -> String {
+    match code {
+        Some(code) => code.to_string(),
+        None => "from a signal".into(),
+    }
+}
@@ -1,93 +1,1560 @@
 //! Check and update copyright of file.
 
+use crate::patch::unified_diff;
 use crate::CError;
 use futures::join;
 use futures::Future;
 use regex::Regex;
-use std::io::{BufRead, BufReader};
+use std::fs::Permissions;
+use std::io::Cursor;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context, Poll};
 use std::{path::Path, path::PathBuf};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::io::{BufReader, BufWriter};
 
+/// Outcome of checking the copyright of a single file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileStatus {
+    /// The file has a correct copyright.
+    Unchanged,
+    /// The years of an existing copyright were (or would be in check mode) updated.
+    Updated {
+        line_nr: usize,
+        found_years: String,
+        years: String,
+    },
+    /// A copyright in a different format was (or would be in check mode) reformatted.
+    Reformatted {
+        line_nr: usize,
+        copyright_line: String,
+    },
+    /// A copyright was (or would be in check mode) added.
+    Added { years: String },
+    /// A copyright was (or would be in check mode) removed.
+    Removed { line_nr: usize },
+    /// The file was skipped according to the options.
+    Skipped,
+}
+
+impl FileStatus {
+    pub fn needs_change(&self) -> bool {
+        matches!(
+            self,
+            FileStatus::Updated { .. }
+                | FileStatus::Reformatted { .. }
+                | FileStatus::Added { .. }
+                | FileStatus::Removed { .. }
+        )
+    }
+
+    /// Describe the change of a file for the report.
+    pub fn message(&self, filepath: &Path) -> Option<String> {
+        match self {
+            FileStatus::Updated {
+                line_nr,
+                found_years,
+                years,
+            } => Some(format!(
+                "File {} has copyright with year(s) {} on line {} but should have {}",
+                filepath.display(),
+                found_years,
+                line_nr,
+                years
+            )),
+            FileStatus::Reformatted {
+                line_nr,
+                copyright_line,
+            } => Some(format!(
+                "File {} has copyright in a different format on line {}, reformat it to {}",
+                filepath.display(),
+                line_nr,
+                copyright_line
+            )),
+            FileStatus::Added { years } => Some(format!(
+                "File {} has no copyright but should have {}",
+                filepath.display(),
+                years
+            )),
+            FileStatus::Removed { line_nr } => Some(format!(
+                "File {} has copyright on line {}, remove it",
+                filepath.display(),
+                line_nr
+            )),
+            FileStatus::Unchanged | FileStatus::Skipped => None,
+        }
+    }
+}
+
+/// Number of lines at the top of a file which are searched for a copyright.
+const HEADER_LINES: usize = 3;
+
+/// Where a new copyright is added to a file.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Placement<'a> {
+    /// Prefixes of first lines which have to stay above the copyright, e.g. `#!`
+    pub first_line_prefixes: Vec<&'a str>,
+    /// Put a blank line between such a first line and the copyright
+    pub blank_line_after_first_line: bool,
+    /// Number of blank lines between the copyright and the rest of the file
+    pub blank_lines_after_copyright: usize,
+    /// Line ending of added lines, the one of the first line of the file if
+    /// not given
+    pub line_ending: Option<LineEnding>,
+    /// End written files with a line ending if they lack one
+    pub trailing_newline: bool,
+}
+
+/// Line ending written after added lines, lines which are replaced keep theirs.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    pub fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            LineEnding::Lf => b"\n",
+            LineEnding::Crlf => b"\r\n",
+        }
+    }
+
+    /// Line ending of the first line in `buf`, `Lf` if it has none.
+    fn of_first_line(buf: &[u8]) -> LineEnding {
+        match buf.iter().position(|b| *b == b'\n') {
+            Some(end) if buf[..end].ends_with(b"\r") => LineEnding::Crlf,
+            _ => LineEnding::Lf,
+        }
+    }
+}
+
+/// Which changes `read_write_copyright` makes to a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Changes {
+    /// Only detect changes without writing them
+    pub check_only: bool,
+    /// Add copyrights to files without one
+    pub add: bool,
+    /// Update existing copyrights with outdated years or format
+    pub update: bool,
+    /// Update copyrights whose only change is a later end year, see `is_range_extension`
+    pub extend_range: bool,
+    /// Remove copyrights instead of adding or updating them
+    pub remove: bool,
+}
+
+impl Default for Changes {
+    fn default() -> Self {
+        Changes {
+            check_only: false,
+            add: true,
+            update: true,
+            extend_range: true,
+            remove: false,
+        }
+    }
+}
+
+/// Regexes to find copyrights in a file, the years (if any) are captured as group
+/// `years`, the holder as group `holder` if it is kept as found.
+#[derive(Debug, Clone)]
+pub struct CopyrightRegexes {
+    /// Copyright in the current format
+    pub current: Arc<Regex>,
+    /// Copyright in a previous format which is reformatted
+    pub previous: Option<Arc<Regex>>,
+    /// Copyright of any holder, ours is added below them instead of on top
+    pub any_holder: Option<Arc<Regex>>,
+    /// Copyright with an equivalent comment sign, which is rewritten with ours
+    pub equivalent: Vec<Arc<Regex>>,
+}
+
+impl CopyrightRegexes {
+    pub fn new(current: Arc<Regex>) -> Self {
+        CopyrightRegexes {
+            current,
+            previous: None,
+            any_holder: None,
+            equivalent: Vec::new(),
+        }
+    }
+}
+
+/// How the copyright line is written to a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    /// Replace the line with this index
+    Replace(usize),
+    /// Insert below the line with this index
+    InsertAfter(usize),
+    /// Insert on top, respecting the placement
+    Insert,
+    /// Remove the line with this index and a blank line following it
+    Remove(usize),
+}
+
+/// Check the copyright of a file and add or update it according to `changes`.
+///
+/// The file is opened once. Its first lines are scanned for a copyright and
+/// kept in memory, the rest is streamed from the same handle into a temporary
+/// file which replaces the original, so large files are never fully read into
+/// memory and a file is written at most once.
 pub async fn read_write_copyright(
     filepath: PathBuf,
-    regex: Arc<Regex>,
+    regexes: &CopyrightRegexes,
     years_fut: impl Future<Output = String>,
     copyright_line: impl Future<Output = String>,
-) -> Result<(), CError> {
+    placement: &Placement<'_>,
+    changes: Changes,
+) -> Result<FileStatus, CError> {
     let (years, copyright_line) = join!(years_fut, copyright_line);
+    match check_copyright(&filepath, regexes, years, &copyright_line, changes).await? {
+        Checked::Done(status) => Ok(status),
+        Checked::Change(change) => {
+            if !changes.check_only {
+                let reader = Cursor::new(change.header).chain(change.reader);
+                write_copyright(
+                    &filepath,
+                    reader,
+                    change.permissions,
+                    &change.line,
+                    change.edit,
+                    placement,
+                )
+                .await?;
+            }
+            Ok(change.status)
+        }
+    }
+}
+
+/// Check the copyright of a file like `read_write_copyright` without writing
+/// it, the change is returned as unified diff of the file at `patch_path`.
+///
+/// Unlike `read_write_copyright`, files needing a change are fully read.
+pub async fn diff_copyright(
+    filepath: PathBuf,
+    patch_path: &str,
+    regexes: &CopyrightRegexes,
+    years_fut: impl Future<Output = String>,
+    copyright_line: impl Future<Output = String>,
+    placement: &Placement<'_>,
+    changes: Changes,
+) -> Result<(FileStatus, Option<String>), CError> {
+    let (years, copyright_line) = join!(years_fut, copyright_line);
+    match check_copyright(&filepath, regexes, years, &copyright_line, changes).await? {
+        Checked::Done(status) => Ok((status, None)),
+        Checked::Change(mut change) => {
+            let mut old = change.header;
+            change.reader.read_to_end(&mut old).await?;
+            let mut new = Vec::new();
+            stream_with_copyright(
+                &filepath,
+                Cursor::new(old.as_slice()),
+                &mut new,
+                &change.line,
+                change.edit,
+                placement,
+            )
+            .await?;
+            let diff = unified_diff(
+                patch_path,
+                std::str::from_utf8(&old)?,
+                std::str::from_utf8(&new)?,
+            );
+            Ok((change.status, Some(diff)))
+        }
+    }
+}
+
+/// Outcome of checking a file before anything is written.
+enum Checked {
+    /// Nothing to write, the file is up to date or skipped
+    Done(FileStatus),
+    /// The file needs a change
+    Change(Box<Change>),
+}
 
-    // This could be re-written to read the file asynchronously until EOF or the first n
-    // newlines are found.
-    let file = std::fs::File::open(&filepath)
-        .map_err(|_| CError::ReadError(filepath.display().to_string()))?;
-    let file_header = BufReader::new(file).lines().take(3);
+/// Change of a file with the handle it is read from.
+struct Change {
+    status: FileStatus,
+    edit: Edit,
+    line: String,
+    /// Bytes already read from `reader`
+    header: Vec<u8>,
+    reader: BufReader<tokio::fs::File>,
+    permissions: Permissions,
+}
 
-    for (line_nr, line_) in file_header.enumerate() {
-        if let Ok(line_) = line_ {
-            if let Some(cap) = regex.captures_iter(&line_).take(1).next() {
-                if years == &cap[1] {
+async fn check_copyright(
+    filepath: &Path,
+    regexes: &CopyrightRegexes,
+    years: String,
+    copyright_line: &str,
+    changes: Changes,
+) -> Result<Checked, CError> {
+    // The file is read once, the header lines which are scanned for a
+    // copyright are kept and written with the rest of the same handle, so the
+    // line found and the line rewritten are always the same
+    let file = match tokio::fs::File::open(&filepath).await {
+        Ok(file) => file,
+        // Files listed before may be removed by the time they are processed
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            log::debug!("Skipping {} which was removed", filepath.display());
+            return Ok(Checked::Done(FileStatus::Skipped));
+        }
+        Err(_) => return Err(CError::ReadError(filepath.display().to_string())),
+    };
+    let permissions = file.metadata().await?.permissions();
+    let mut reader = BufReader::new(file);
+    if changes.remove {
+        return remove_copyright(filepath, reader, permissions, regexes, years).await;
+    }
+    let Scan {
+        status,
+        edit,
+        line,
+        header,
+    } = scan_header(filepath, &mut reader, regexes, years, copyright_line).await?;
+    if status == FileStatus::Unchanged {
+        return Ok(Checked::Done(status));
+    }
+
+    match edit {
+        Edit::Replace(line_nr) => log::debug!(
+            "File {} has a copyright on line index {}: {:?}",
+            filepath.display(),
+            line_nr,
+            status
+        ),
+        Edit::InsertAfter(line_nr) => log::debug!(
+            "File {} has no copyright of ours but one of another holder on line index {}",
+            filepath.display(),
+            line_nr
+        ),
+        Edit::Insert => log::debug!(
+            "File {} has no copyright in the first {} lines",
+            filepath.display(),
+            HEADER_LINES
+        ),
+        Edit::Remove(_) => unreachable!("Copyrights are only removed by `remove_copyright`"),
+    }
+
+    if matches!(status, FileStatus::Added { .. }) && !changes.add {
+        log::info!(
+            "File {} has no copyright, leaving it as is",
+            filepath.display()
+        );
+        return Ok(Checked::Done(FileStatus::Skipped));
+    }
+    if matches!(
+        status,
+        FileStatus::Updated { .. } | FileStatus::Reformatted { .. }
+    ) && !changes.update
+    {
+        log::info!(
+            "File {} has an outdated copyright, leaving it as is",
+            filepath.display()
+        );
+        return Ok(Checked::Done(FileStatus::Skipped));
+    }
+    if let FileStatus::Updated {
+        found_years, years, ..
+    } = &status
+    {
+        if !changes.extend_range && is_range_extension(found_years, years) {
+            log::info!(
+                "File {} only needs its years extended from {} to {}, leaving it as is",
+                filepath.display(),
+                found_years,
+                years
+            );
+            return Ok(Checked::Done(FileStatus::Skipped));
+        }
+    }
+
+    Ok(Checked::Change(Box::new(Change {
+        status,
+        edit,
+        line,
+        header,
+        reader,
+        permissions,
+    })))
+}
+
+/// Check for a copyright to remove from a file.
+///
+/// The header is scanned as for an update with an empty copyright line, so
+/// the line to write is what is left of the line with the copyright. Only if
+/// nothing but whitespace is left, the whole line is removed.
+async fn remove_copyright(
+    filepath: &Path,
+    mut reader: BufReader<tokio::fs::File>,
+    permissions: Permissions,
+    regexes: &CopyrightRegexes,
+    years: String,
+) -> Result<Checked, CError> {
+    let Scan {
+        edit, line, header, ..
+    } = scan_header(filepath, &mut reader, regexes, years, "").await?;
+    let line_nr = match edit {
+        Edit::Replace(line_nr) => line_nr,
+        _ => {
+            log::debug!("File {} has no copyright to remove", filepath.display());
+            return Ok(Checked::Done(FileStatus::Unchanged));
+        }
+    };
+    let edit = match line.trim().is_empty() {
+        true => Edit::Remove(line_nr),
+        // Keep the rest of a line with a note which was matched within it
+        false => Edit::Replace(line_nr),
+    };
+    log::debug!(
+        "File {} has a copyright to remove on line index {}",
+        filepath.display(),
+        line_nr
+    );
+
+    Ok(Checked::Change(Box::new(Change {
+        status: FileStatus::Removed { line_nr },
+        edit,
+        line: line.trim_end().to_owned(),
+        header,
+        reader,
+        permissions,
+    })))
+}
+
+/// Result of scanning the header of a file for a copyright.
+struct Scan {
+    status: FileStatus,
+    edit: Edit,
+    /// Line to write, the copyright line with the rest of a replaced line
+    /// kept around a note which was matched within it
+    line: String,
+    /// Bytes of the lines read from the file
+    header: Vec<u8>,
+}
+
+/// Read the first lines of a file from `reader` to find its copyright.
+async fn scan_header(
+    filepath: &Path,
+    reader: &mut (impl AsyncBufRead + Unpin),
+    regexes: &CopyrightRegexes,
+    years: String,
+    copyright_line: &str,
+) -> Result<Scan, CError> {
+    let mut header = Vec::new();
+    let mut header_lines = HEADER_LINES;
+    let mut status = FileStatus::Added {
+        years: years.clone(),
+    };
+    let mut copyright_line_nr = None;
+    let mut last_holder_line_nr = None;
+    let mut line = copyright_line.to_owned();
+
+    for line_nr in 0.. {
+        if line_nr >= header_lines {
+            break;
+        }
+        let line_start = header.len();
+        if reader.read_until(b'\n', &mut header).await? == 0 {
+            break;
+        }
+        if let Ok(line_) = std::str::from_utf8(&header[line_start..]) {
+            let line_ = line_.strip_suffix('\n').unwrap_or(line_);
+            let line_ = line_.strip_suffix('\r').unwrap_or(line_);
+            let found = match regexes.current.captures(line_) {
+                Some(cap) => Some((cap, true)),
+                None => regexes
+                    .equivalent
+                    .iter()
+                    .find_map(|regex| regex.captures(line_))
+                    .map(|cap| (cap, false)),
+            };
+            if let Some((cap, same_comment_sign)) = found {
+                // Copyrights without years are up to date as long as they exist
+                let found_years = cap.name("years").map_or(years.as_str(), |m| m.as_str());
+                line = match (cap.name("holder"), cap.name("years")) {
+                    // Notes of kept holders are left as they are apart from the years
+                    (Some(_), Some(found)) if same_comment_sign => {
+                        [&line_[..found.start()], &years, &line_[found.end()..]].concat()
+                    }
+                    (Some(_), None) if same_comment_sign => line_.to_owned(),
+                    _ => replace_match(line_, cap.get(0).unwrap(), copyright_line),
+                };
+                if years == found_years && line_ == line {
                     log::debug!(
                         "File {} has correct copyright with years {}",
                         filepath.display(),
                         years
                     );
-                    return Ok(());
+                    status = FileStatus::Unchanged;
+                } else if years == found_years {
+                    // Matching tolerates notes which differ from the generated
+                    // line, e.g. in trailing whitespace, rewrite them canonically
+                    status = FileStatus::Reformatted {
+                        line_nr,
+                        copyright_line: line.clone(),
+                    };
                 } else {
-                    println!(
-                        "File {} has copyright with year(s) {} on line {} but should have {}",
-                        filepath.display(),
-                        &cap[1],
+                    status = FileStatus::Updated {
                         line_nr,
-                        years
-                    );
-                    return write_copyright(&filepath, &copyright_line, Some(line_nr)).await;
+                        found_years: found_years.to_owned(),
+                        years,
+                    };
+                }
+                copyright_line_nr = Some(line_nr);
+                break;
+            }
+
+            if let Some(old_regex) = regexes.previous.as_ref() {
+                if let Some(m) = old_regex.find(line_) {
+                    line = replace_match(line_, m, copyright_line);
+                    status = FileStatus::Reformatted {
+                        line_nr,
+                        copyright_line: line.clone(),
+                    };
+                    copyright_line_nr = Some(line_nr);
+                    break;
+                }
+            }
+
+            if let Some(any_holder) = regexes.any_holder.as_ref() {
+                if any_holder.is_match(line_) {
+                    // Our copyright may follow the ones of other holders
+                    last_holder_line_nr = Some(line_nr);
+                    header_lines = header_lines.max(line_nr + 2);
                 }
             }
         }
     }
 
-    println!(
-        "File {} has no copyright but should have {}",
-        filepath.display(),
-        years
-    );
-    write_copyright(&filepath, &copyright_line, None).await
+    let edit = match (copyright_line_nr, last_holder_line_nr) {
+        (Some(line_nr), _) => Edit::Replace(line_nr),
+        (None, Some(line_nr)) => Edit::InsertAfter(line_nr),
+        (None, None) => Edit::Insert,
+    };
+    Ok(Scan {
+        status,
+        edit,
+        line,
+        header,
+    })
+}
+
+/// Check if `years` only extend `found_years` to a later end year, e.g. from
+/// `2019` or `2019-2021` to `2019-2023`. Years in two-digit format are
+/// compared by their digits.
+fn is_range_extension(found_years: &str, years: &str) -> bool {
+    let bounds = |years: &str| {
+        let (start, end) = years.split_once('-').unwrap_or((years, years));
+        let end = end.trim_start_matches('\'').parse::<u32>().ok()?;
+        Some((start.to_owned(), end))
+    };
+    match (bounds(found_years), bounds(years)) {
+        (Some((found_start, found_end)), Some((start, end))) => {
+            found_start == start && found_end < end
+        }
+        _ => false,
+    }
+}
+
+/// Replace the matched note in `line` by `copyright_line`. Anchored regexes
+/// match whole lines, so only notes matched within a line keep the rest of it.
+/// The indentation of a note is kept in any case.
+fn replace_match(line: &str, m: regex::Match, copyright_line: &str) -> String {
+    let indent = m.as_str().len() - m.as_str().trim_start().len();
+    [
+        &line[..m.start() + indent],
+        copyright_line,
+        &line[m.end()..],
+    ]
+    .concat()
 }
 
 async fn write_copyright(
     filepath: &Path,
+    reader: impl AsyncBufRead + Unpin,
+    permissions: Permissions,
     copyright_line: &str,
-    line_nr: Option<usize>,
+    edit: Edit,
+    placement: &Placement<'_>,
 ) -> Result<(), CError> {
-    let mut file = tokio::fs::File::open(filepath)
-        .await
-        .map_err(|_| CError::ReadError(filepath.display().to_string()))?;
-    let mut data = Vec::new();
-    file.read_to_end(&mut data).await?;
-    let mut data: Vec<&str> = std::str::from_utf8(&data)?.split("\n").collect();
-
-    match line_nr {
-        Some(line_nr) => {
-            data[line_nr] = &copyright_line;
-        }
-        None => {
-            if data.len() >= 1 && data[0].starts_with("#!") {
-                // Insert copyright on the second line for shell scripts
-                // that might have a shebang line
-                data.insert(1, copyright_line);
+    // Write to a file next to the original and move it in place afterwards so
+    // that the original is never truncated before the rest of it was copied
+    let tmp_path = tmp_path_for(filepath);
+    let written = match tokio::fs::File::create(&tmp_path).await {
+        Ok(tmp_file) => {
+            let writer = BufWriter::new(tmp_file);
+            stream_with_copyright(filepath, reader, writer, copyright_line, edit, placement).await
+        }
+        Err(e) => Err(e),
+    };
+
+    let moved = match written {
+        Ok(()) => match tokio::fs::set_permissions(&tmp_path, permissions).await {
+            Ok(()) => tokio::fs::rename(&tmp_path, filepath).await,
+            Err(e) => Err(e),
+        },
+        Err(e) => Err(e),
+    };
+    if moved.is_err() {
+        let _ = tokio::fs::remove_file(&tmp_path).await;
+        return Err(CError::WriteError(filepath.display().to_string()));
+    }
+
+    Ok(())
+}
+
+/// Copy `reader` to `writer` with the copyright line written according to
+/// `edit`. Only the lines in front of the copyright are buffered, the rest is
+/// streamed.
+async fn stream_with_copyright(
+    filepath: &Path,
+    mut reader: impl AsyncBufRead + Unpin,
+    writer: impl AsyncWrite + Unpin,
+    copyright_line: &str,
+    edit: Edit,
+    placement: &Placement<'_>,
+) -> std::io::Result<()> {
+    let mut line = Vec::new();
+    let newline = match placement.line_ending {
+        Some(line_ending) => line_ending,
+        None => LineEnding::of_first_line(reader.fill_buf().await?),
+    }
+    .as_bytes();
+    let mut writer = LastByte {
+        inner: writer,
+        last: None,
+    };
+
+    match edit {
+        Edit::Replace(line_nr) => {
+            log::debug!(
+                "File {}: replacing line index {} with the copyright",
+                filepath.display(),
+                line_nr
+            );
+            for _ in 0..line_nr {
+                line.clear();
+                reader.read_until(b'\n', &mut line).await?;
+                writer.write_all(&line).await?;
+            }
+            line.clear();
+            reader.read_until(b'\n', &mut line).await?;
+            writer.write_all(copyright_line.as_bytes()).await?;
+            if line.ends_with(b"\r\n") {
+                writer.write_all(b"\r\n").await?;
+            } else if line.ends_with(b"\n") {
+                writer.write_all(b"\n").await?;
+            }
+        }
+        Edit::Remove(line_nr) => {
+            log::debug!(
+                "File {}: removing the copyright on line index {}",
+                filepath.display(),
+                line_nr
+            );
+            for _ in 0..line_nr {
+                line.clear();
+                reader.read_until(b'\n', &mut line).await?;
+                writer.write_all(&line).await?;
+            }
+            line.clear();
+            reader.read_until(b'\n', &mut line).await?;
+            // A blank line separating the copyright from the rest goes with it
+            line.clear();
+            reader.read_until(b'\n', &mut line).await?;
+            if !line.iter().all(u8::is_ascii_whitespace) {
+                writer.write_all(&line).await?;
+            }
+        }
+        Edit::InsertAfter(line_nr) => {
+            log::debug!(
+                "File {}: inserting the copyright after line index {}",
+                filepath.display(),
+                line_nr
+            );
+            for _ in 0..=line_nr {
+                line.clear();
+                reader.read_until(b'\n', &mut line).await?;
+                writer.write_all(&line).await?;
+            }
+            if line.ends_with(b"\n") {
+                writer.write_all(copyright_line.as_bytes()).await?;
+                writer.write_all(newline).await?;
+            } else {
+                writer.write_all(newline).await?;
+                writer.write_all(copyright_line.as_bytes()).await?;
+            }
+        }
+        Edit::Insert => {
+            reader.read_until(b'\n', &mut line).await?;
+            if placement
+                .first_line_prefixes
+                .iter()
+                .any(|p| line.starts_with(p.as_bytes()))
+            {
+                // Insert copyright on the second line for files with a first
+                // line that has to stay on top, e.g. a shebang
+                log::debug!(
+                    "File {}: inserting the copyright after first line {:?}",
+                    filepath.display(),
+                    String::from_utf8_lossy(&line).trim_end()
+                );
+                writer.write_all(&line).await?;
+                if !line.ends_with(b"\n") {
+                    writer.write_all(newline).await?;
+                }
+                if placement.blank_line_after_first_line {
+                    writer.write_all(newline).await?;
+                }
+                writer.write_all(copyright_line.as_bytes()).await?;
+                if line.ends_with(b"\n") {
+                    writer.write_all(newline).await?;
+                }
+                line.clear();
             } else {
-                data.insert(0, copyright_line);
+                log::debug!(
+                    "File {}: inserting the copyright at the top",
+                    filepath.display()
+                );
+                writer.write_all(copyright_line.as_bytes()).await?;
+                writer.write_all(newline).await?;
+            }
+
+            let at_end = line.is_empty() && reader.fill_buf().await?.is_empty();
+            if !at_end {
+                for _ in 0..placement.blank_lines_after_copyright {
+                    writer.write_all(newline).await?;
+                }
+            }
+            writer.write_all(&line).await?;
+        }
+    }
+
+    tokio::io::copy_buf(&mut reader, &mut writer).await?;
+    if placement.trailing_newline && writer.last != Some(b'\n') {
+        writer.write_all(newline).await?;
+    }
+    // tokio finishes writes in the background, flush to be done when returning
+    writer.flush().await?;
+
+    Ok(())
+}
+
+/// Writer which remembers the last byte written to `inner`.
+struct LastByte<W> {
+    inner: W,
+    last: Option<u8>,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for LastByte<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll {
+            if written > 0 {
+                self.last = Some(buf[written - 1]);
             }
         }
+        poll
     }
 
-    let mut file = tokio::fs::File::create(filepath)
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+fn tmp_path_for(filepath: &Path) -> PathBuf {
+    let filename = filepath
+        .file_name()
+        .map(|f| f.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    filepath.with_file_name(format!(".{}.git_copyright", filename))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{is_range_extension, read_write_copyright, scan_header, write_copyright};
+    use super::{Changes, CopyrightRegexes, Edit, FileStatus, LineEnding, Placement};
+    use futures::future::ready;
+    use regex::Regex;
+    use std::io::Cursor;
+    use std::sync::Arc;
+    use tokio::io::AsyncReadExt;
+
+    async fn add_copyright(content: &str, first_line_prefixes: &[&str]) -> String {
+        let placement = Placement {
+            first_line_prefixes: first_line_prefixes.to_vec(),
+            ..Default::default()
+        };
+        add_copyright_with(content, &placement).await
+    }
+
+    async fn add_copyright_with(content: &str, placement: &Placement<'_>) -> String {
+        let dir = tempfile::tempdir().unwrap();
+        let filepath = dir.path().join("file");
+        std::fs::write(&filepath, content).unwrap();
+
+        let regex = Arc::new(Regex::new(r"^# Copyright (?P<years>\d{4})$").unwrap());
+        let status = read_write_copyright(
+            filepath.clone(),
+            &CopyrightRegexes::new(regex),
+            ready("2020".into()),
+            ready("# Copyright 2020".into()),
+            placement,
+            Changes::default(),
+        )
         .await
-        .map_err(|_| CError::WriteError(filepath.display().to_string()))?;
-    file.write_all(data.join("\n").as_bytes())
+        .unwrap();
+        assert_eq!(
+            status,
+            FileStatus::Added {
+                years: "2020".into()
+            }
+        );
+
+        std::fs::read_to_string(filepath).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_copyright_after_shebang() {
+        assert_eq!(
+            add_copyright("#!/bin/sh\necho hi\n", &["#!"]).await,
+            "#!/bin/sh\n# Copyright 2020\necho hi\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_copyright_after_xml_declaration() {
+        assert_eq!(
+            add_copyright("<?xml version=\"1.0\"?>\n<root/>\n", &["<?xml"]).await,
+            "<?xml version=\"1.0\"?>\n# Copyright 2020\n<root/>\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_copyright_after_php_tag() {
+        assert_eq!(
+            add_copyright("<?php\necho 'hi';\n", &["<?php"]).await,
+            "<?php\n# Copyright 2020\necho 'hi';\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_copyright_on_top_if_not_shebang_capable() {
+        assert_eq!(
+            add_copyright("#![allow(unused)]\nfn main() {}\n", &[]).await,
+            "# Copyright 2020\n#![allow(unused)]\nfn main() {}\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_removed_file_is_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let regex = Arc::new(Regex::new(r"^# Copyright (?P<years>\d{4})$").unwrap());
+        let status = read_write_copyright(
+            dir.path().join("removed"),
+            &CopyrightRegexes::new(regex),
+            ready("2020".into()),
+            ready("# Copyright 2020".into()),
+            &Placement::default(),
+            Changes::default(),
+        )
         .await
-        .map_err(|_| CError::WriteError(filepath.display().to_string()))?;
+        .unwrap();
+        assert_eq!(status, FileStatus::Skipped);
+        assert!(!dir.path().join("removed").exists());
+    }
 
-    Ok(())
+    #[tokio::test]
+    async fn test_equivalent_comment_sign_is_rewritten() {
+        let dir = tempfile::tempdir().unwrap();
+        let filepath = dir.path().join("main.c");
+        std::fs::write(&filepath, "// Copyright 2019\nint main() {}\n").unwrap();
+
+        let regexes = CopyrightRegexes {
+            equivalent: vec![Arc::new(
+                Regex::new(r"^// Copyright (?P<years>\d{4}(-\d{4})?)$").unwrap(),
+            )],
+            ..CopyrightRegexes::new(Arc::new(
+                Regex::new(r"^/\* Copyright (?P<years>\d{4}(-\d{4})?) \*/$").unwrap(),
+            ))
+        };
+        for (years, expected_status) in [
+            (
+                "2019",
+                FileStatus::Reformatted {
+                    line_nr: 0,
+                    copyright_line: "/* Copyright 2019 */".into(),
+                },
+            ),
+            ("2019", FileStatus::Unchanged),
+        ] {
+            let status = read_write_copyright(
+                filepath.clone(),
+                &regexes,
+                ready(years.into()),
+                ready(format!("/* Copyright {} */", years)),
+                &Placement::default(),
+                Changes::default(),
+            )
+            .await
+            .unwrap();
+            assert_eq!(status, expected_status);
+            assert_eq!(
+                std::fs::read_to_string(&filepath).unwrap(),
+                "/* Copyright 2019 */\nint main() {}\n"
+            );
+        }
+
+        std::fs::write(&filepath, "// Copyright 2019\nint main() {}\n").unwrap();
+        let status = read_write_copyright(
+            filepath.clone(),
+            &regexes,
+            ready("2019-2020".into()),
+            ready("/* Copyright 2019-2020 */".into()),
+            &Placement::default(),
+            Changes::default(),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(status, FileStatus::Updated { line_nr: 0, .. }));
+        assert_eq!(
+            std::fs::read_to_string(&filepath).unwrap(),
+            "/* Copyright 2019-2020 */\nint main() {}\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_trailing_whitespace_is_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let filepath = dir.path().join("file");
+        std::fs::write(&filepath, "# Copyright 2020 \necho hi\n").unwrap();
+
+        let regex = Arc::new(Regex::new(r"^# Copyright (?P<years>\d{4})\s*$").unwrap());
+        let status = read_write_copyright(
+            filepath.clone(),
+            &CopyrightRegexes::new(regex),
+            ready("2020".into()),
+            ready("# Copyright 2020".into()),
+            &Placement::default(),
+            Changes::default(),
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(status, FileStatus::Reformatted { line_nr: 0, .. }));
+        assert_eq!(
+            std::fs::read_to_string(filepath).unwrap(),
+            "# Copyright 2020\necho hi\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_indented_copyright_keeps_indentation() {
+        let dir = tempfile::tempdir().unwrap();
+        let filepath = dir.path().join("file");
+        std::fs::write(&filepath, "mod a {\n    // Copyright 2020\n}\n").unwrap();
+
+        let regex =
+            Arc::new(Regex::new(r"^\s*// Copyright (?P<years>\d{4}(-\d{4})?)\s*$").unwrap());
+        let status = read_write_copyright(
+            filepath.clone(),
+            &CopyrightRegexes::new(Arc::clone(&regex)),
+            ready("2020-2021".into()),
+            ready("// Copyright 2020-2021".into()),
+            &Placement::default(),
+            Changes::default(),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(status, FileStatus::Updated { line_nr: 1, .. }));
+        assert_eq!(
+            std::fs::read_to_string(&filepath).unwrap(),
+            "mod a {\n    // Copyright 2020-2021\n}\n"
+        );
+
+        let status = read_write_copyright(
+            filepath.clone(),
+            &CopyrightRegexes::new(regex),
+            ready("2020-2021".into()),
+            ready("// Copyright 2020-2021".into()),
+            &Placement::default(),
+            Changes::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, FileStatus::Unchanged);
+    }
+
+    #[tokio::test]
+    async fn test_embedded_copyright_is_updated_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let filepath = dir.path().join("file");
+        std::fs::write(&filepath, "  {/* Copyright 2020 */}\n<App />\n").unwrap();
+
+        let regex = Arc::new(Regex::new(r"/\* Copyright (?P<years>\d{4}) \*/").unwrap());
+        let status = read_write_copyright(
+            filepath.clone(),
+            &CopyrightRegexes::new(Arc::clone(&regex)),
+            ready("2021".into()),
+            ready("/* Copyright 2021 */".into()),
+            &Placement::default(),
+            Changes::default(),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(status, FileStatus::Updated { line_nr: 0, .. }));
+        assert_eq!(
+            std::fs::read_to_string(&filepath).unwrap(),
+            "  {/* Copyright 2021 */}\n<App />\n"
+        );
+
+        let status = read_write_copyright(
+            filepath.clone(),
+            &CopyrightRegexes::new(regex),
+            ready("2021".into()),
+            ready("/* Copyright 2021 */".into()),
+            &Placement::default(),
+            Changes::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, FileStatus::Unchanged);
+    }
+
+    #[tokio::test]
+    async fn test_crlf_file_is_read_once_and_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let filepath = dir.path().join("file");
+        let content = "#!/bin/sh\r\n# Copyright 2020\r\necho hi\r\n";
+        std::fs::write(&filepath, content).unwrap();
+
+        let regex = Arc::new(Regex::new(r"^# Copyright (?P<years>\d{4})$").unwrap());
+        let status = read_write_copyright(
+            filepath.clone(),
+            &CopyrightRegexes::new(Arc::clone(&regex)),
+            ready("2020".into()),
+            ready("# Copyright 2020".into()),
+            &Placement::default(),
+            Changes::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, FileStatus::Unchanged);
+        assert_eq!(std::fs::read_to_string(&filepath).unwrap(), content);
+
+        let status = read_write_copyright(
+            filepath.clone(),
+            &CopyrightRegexes::new(Arc::clone(&regex)),
+            ready("2020-2021".into()),
+            ready("# Copyright 2020-2021".into()),
+            &Placement::default(),
+            Changes {
+                check_only: true,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert!(matches!(status, FileStatus::Updated { line_nr: 1, .. }));
+        assert_eq!(std::fs::read_to_string(&filepath).unwrap(), content);
+
+        let status = read_write_copyright(
+            filepath.clone(),
+            &CopyrightRegexes::new(regex),
+            ready("2020-2021".into()),
+            ready("# Copyright 2020-2021".into()),
+            &Placement::default(),
+            Changes::default(),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(status, FileStatus::Updated { line_nr: 1, .. }));
+        assert_eq!(
+            std::fs::read(&filepath).unwrap(),
+            b"#!/bin/sh\r\n# Copyright 2020-2021\r\necho hi\r\n"
+        );
+    }
+
+    #[test]
+    fn test_is_range_extension() {
+        assert!(is_range_extension("2019", "2019-2023"));
+        assert!(is_range_extension("2019-2021", "2019-2023"));
+        assert!(is_range_extension("'19-'21", "'19-'23"));
+        assert!(!is_range_extension("2019-2023", "2019-2023"));
+        assert!(!is_range_extension("2018-2021", "2019-2023"));
+        assert!(!is_range_extension("2019-2024", "2019-2023"));
+        assert!(!is_range_extension("2019-2021", "2019"));
+        assert!(!is_range_extension("2012", "2019-2023"));
+    }
+
+    #[tokio::test]
+    async fn test_no_extend_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let regex = Arc::new(Regex::new(r"^# Copyright (?P<years>\d{4}(-\d{4})?)$").unwrap());
+        let changes = Changes {
+            extend_range: false,
+            ..Default::default()
+        };
+        for (content, expected) in [
+            // Only the end year advances, the copyright is left as is
+            ("# Copyright 2019-2021\n", "# Copyright 2019-2021\n"),
+            ("# Copyright 2019\n", "# Copyright 2019\n"),
+            // Wrong years are still fixed and missing copyrights added
+            ("# Copyright 2012-2015\n", "# Copyright 2019-2023\n"),
+            ("# Copyright 2019-2025\n", "# Copyright 2019-2023\n"),
+            ("echo hi\n", "# Copyright 2019-2023\necho hi\n"),
+        ] {
+            let filepath = dir.path().join("file");
+            std::fs::write(&filepath, content).unwrap();
+            let status = read_write_copyright(
+                filepath.clone(),
+                &CopyrightRegexes::new(Arc::clone(&regex)),
+                ready("2019-2023".into()),
+                ready("# Copyright 2019-2023".into()),
+                &Placement::default(),
+                changes,
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                status == FileStatus::Skipped,
+                content == expected,
+                "{}",
+                content
+            );
+            assert_eq!(std::fs::read_to_string(&filepath).unwrap(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        let regex =
+            Arc::new(Regex::new(r"(?:^|#\s)Copyright (?P<years>\d{4}(-\d{4})?) Org\s*$").unwrap());
+        let changes = Changes {
+            remove: true,
+            ..Default::default()
+        };
+        for (content, expected, status) in [
+            // The notice and a blank line following it are removed
+            (
+                "# Copyright 2019-2021 Org\n\nimport os\n",
+                "import os\n",
+                FileStatus::Removed { line_nr: 0 },
+            ),
+            (
+                "# Copyright 2019 Org\nimport os\n\nprint(os.name)\n",
+                "import os\n\nprint(os.name)\n",
+                FileStatus::Removed { line_nr: 0 },
+            ),
+            // Shebangs and other lines around the notice are kept
+            (
+                "#!/usr/bin/env python3\n# Copyright 2019 Org\n\nimport os\n",
+                "#!/usr/bin/env python3\nimport os\n",
+                FileStatus::Removed { line_nr: 1 },
+            ),
+            (
+                "#!/bin/sh\r\n# Copyright 2019 Org\r\necho hi\r\n",
+                "#!/bin/sh\r\necho hi\r\n",
+                FileStatus::Removed { line_nr: 1 },
+            ),
+            (
+                "# Copyright 2019 Org",
+                "",
+                FileStatus::Removed { line_nr: 0 },
+            ),
+            // Only the notice is removed from a line with other content
+            (
+                "x = 1  # Copyright 2019 Org\ny = 2\n",
+                "x = 1\ny = 2\n",
+                FileStatus::Removed { line_nr: 0 },
+            ),
+            // Notices of others and unrelated comments are left as they are
+            (
+                "# Copyright 2019 Other\n# Copyright notes\n",
+                "# Copyright 2019 Other\n# Copyright notes\n",
+                FileStatus::Unchanged,
+            ),
+        ] {
+            let filepath = dir.path().join("file");
+            std::fs::write(&filepath, content).unwrap();
+            let found = read_write_copyright(
+                filepath.clone(),
+                &CopyrightRegexes::new(Arc::clone(&regex)),
+                ready("2023".into()),
+                ready("# Copyright 2023 Org".into()),
+                &Placement::default(),
+                changes,
+            )
+            .await
+            .unwrap();
+            assert_eq!(found, status, "{}", content);
+            assert_eq!(std::fs::read_to_string(&filepath).unwrap(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shebang_add_then_update() {
+        let dir = tempfile::tempdir().unwrap();
+        let filepath = dir.path().join("run.sh");
+        let regex = Arc::new(Regex::new(r"^# Copyright (?P<years>\d{4}(-\d{4})?)$").unwrap());
+        for (blank_line_after_first_line, blank_lines_after_copyright, added, line_nr) in [
+            (false, 0, "#!/bin/sh\n# Copyright 2020\necho hi\n", 1),
+            (true, 0, "#!/bin/sh\n\n# Copyright 2020\necho hi\n", 2),
+            (true, 1, "#!/bin/sh\n\n# Copyright 2020\n\necho hi\n", 2),
+        ] {
+            let placement = Placement {
+                first_line_prefixes: vec!["#!"],
+                blank_line_after_first_line,
+                blank_lines_after_copyright,
+                ..Default::default()
+            };
+            std::fs::write(&filepath, "#!/bin/sh\necho hi\n").unwrap();
+            for (years, status) in [
+                (
+                    "2020",
+                    FileStatus::Added {
+                        years: "2020".into(),
+                    },
+                ),
+                (
+                    "2020-2021",
+                    FileStatus::Updated {
+                        line_nr,
+                        found_years: "2020".into(),
+                        years: "2020-2021".into(),
+                    },
+                ),
+                ("2020-2021", FileStatus::Unchanged),
+            ] {
+                let found = read_write_copyright(
+                    filepath.clone(),
+                    &CopyrightRegexes::new(Arc::clone(&regex)),
+                    ready(years.to_owned()),
+                    ready(format!("# Copyright {}", years)),
+                    &placement,
+                    Changes::default(),
+                )
+                .await
+                .unwrap();
+                // The copyright written before is found, so none is added twice
+                assert_eq!(found, status);
+                assert_eq!(
+                    std::fs::read_to_string(&filepath).unwrap(),
+                    added.replace("2020", years)
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_large_file_is_copied_unchanged() {
+        let body: String = (0..200_000).map(|i| format!("line {}\n", i)).collect();
+        let content = format!("#!/bin/sh\n{}no newline at the end", body);
+
+        assert_eq!(
+            add_copyright(&content, &["#!"]).await,
+            format!("#!/bin/sh\n# Copyright 2020\n{}no newline at the end", body)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_copyright_after_shebang_without_newline() {
+        assert_eq!(
+            add_copyright("#!/bin/sh", &["#!"]).await,
+            "#!/bin/sh\n# Copyright 2020"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_update_keeps_permissions_and_removes_tmp_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let filepath = dir.path().join("script");
+        std::fs::write(&filepath, "#!/bin/sh\n# Copyright 2019\necho hi\n").unwrap();
+        std::fs::set_permissions(&filepath, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let regex = Arc::new(Regex::new(r"^# Copyright (?P<years>\d{4})$").unwrap());
+        let status = read_write_copyright(
+            filepath.clone(),
+            &CopyrightRegexes::new(regex),
+            ready("2020".into()),
+            ready("# Copyright 2020".into()),
+            &Placement {
+                first_line_prefixes: vec!["#!"],
+                ..Default::default()
+            },
+            Changes::default(),
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(status, FileStatus::Updated { line_nr: 1, .. }));
+        assert_eq!(
+            std::fs::read_to_string(&filepath).unwrap(),
+            "#!/bin/sh\n# Copyright 2020\necho hi\n"
+        );
+        let mode = std::fs::metadata(&filepath).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_blank_lines_after_copyright() {
+        let placement = Placement {
+            blank_lines_after_copyright: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            add_copyright_with("echo hi\n", &placement).await,
+            "# Copyright 2020\n\necho hi\n"
+        );
+        assert_eq!(
+            add_copyright_with("", &placement).await,
+            "# Copyright 2020\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_no_blank_lines_after_copyright() {
+        let placement = Placement {
+            first_line_prefixes: vec!["#!"],
+            blank_line_after_first_line: false,
+            blank_lines_after_copyright: 0,
+            ..Default::default()
+        };
+        assert_eq!(
+            add_copyright_with("echo hi\n\necho ho\n", &placement)
+                .await
+                .as_bytes(),
+            b"# Copyright 2020\necho hi\n\necho ho\n"
+        );
+        assert_eq!(
+            add_copyright_with("#!/bin/sh\necho hi\n", &placement)
+                .await
+                .as_bytes(),
+            b"#!/bin/sh\n# Copyright 2020\necho hi\n"
+        );
+        assert_eq!(
+            add_copyright_with("echo hi", &placement).await.as_bytes(),
+            b"# Copyright 2020\necho hi"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_blank_lines_after_copyright_with_shebang() {
+        let placement = Placement {
+            first_line_prefixes: vec!["#!"],
+            blank_line_after_first_line: false,
+            blank_lines_after_copyright: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            add_copyright_with("#!/bin/sh\necho hi\n", &placement).await,
+            "#!/bin/sh\n# Copyright 2020\n\necho hi\n"
+        );
+        assert_eq!(
+            add_copyright_with("#!/bin/sh\n", &placement).await,
+            "#!/bin/sh\n# Copyright 2020\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_blank_line_between_shebang_and_copyright() {
+        let placement = Placement {
+            first_line_prefixes: vec!["#!"],
+            blank_line_after_first_line: true,
+            blank_lines_after_copyright: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            add_copyright_with("#!/bin/sh\necho hi\n", &placement).await,
+            "#!/bin/sh\n\n# Copyright 2020\n\necho hi\n"
+        );
+        assert_eq!(
+            add_copyright_with("#!/bin/sh", &placement).await,
+            "#!/bin/sh\n\n# Copyright 2020"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_crlf_line_ending_of_added_lines() {
+        let placement = Placement {
+            first_line_prefixes: vec!["#!"],
+            blank_line_after_first_line: true,
+            blank_lines_after_copyright: 1,
+            ..Default::default()
+        };
+        // Taken from the first line of the file
+        assert_eq!(
+            add_copyright_with("#!/bin/sh\r\necho hi\r\n", &placement).await,
+            "#!/bin/sh\r\n\r\n# Copyright 2020\r\n\r\necho hi\r\n"
+        );
+        assert_eq!(
+            add_copyright_with("echo hi\r\n", &placement).await,
+            "# Copyright 2020\r\n\r\necho hi\r\n"
+        );
+        assert_eq!(
+            add_copyright_with("echo hi\n", &placement).await,
+            "# Copyright 2020\n\necho hi\n"
+        );
+        // Given e.g. by an `.editorconfig`
+        let placement = Placement {
+            line_ending: Some(LineEnding::Crlf),
+            ..placement
+        };
+        assert_eq!(
+            add_copyright_with("echo hi\n", &placement).await,
+            "# Copyright 2020\r\n\r\necho hi\n"
+        );
+        let placement = Placement {
+            line_ending: Some(LineEnding::Lf),
+            ..placement
+        };
+        assert_eq!(
+            add_copyright_with("echo hi\r\n", &placement).await,
+            "# Copyright 2020\n\necho hi\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_crlf_line_ending_of_replaced_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let filepath = dir.path().join("run.sh");
+        std::fs::write(&filepath, "#!/bin/sh\r\n# Copyright 2019\r\necho hi\r\n").unwrap();
+        let regex = Arc::new(Regex::new(r"^# Copyright (?P<years>\d{4}(-\d{4})?)$").unwrap());
+        let status = read_write_copyright(
+            filepath.clone(),
+            &CopyrightRegexes::new(regex),
+            ready("2019-2020".into()),
+            ready("# Copyright 2019-2020".into()),
+            // Replaced lines keep their line ending regardless of the placement
+            &Placement::default(),
+            Changes::default(),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(status, FileStatus::Updated { line_nr: 1, .. }));
+        let content = std::fs::read_to_string(&filepath).unwrap();
+        assert_eq!(content, "#!/bin/sh\r\n# Copyright 2019-2020\r\necho hi\r\n");
+        assert!(content.split_inclusive('\n').all(|l| l.ends_with("\r\n")));
+    }
+
+    #[tokio::test]
+    async fn test_trailing_newline() {
+        let placement = Placement {
+            first_line_prefixes: vec!["#!"],
+            trailing_newline: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            add_copyright_with("echo hi", &placement).await,
+            "# Copyright 2020\necho hi\n"
+        );
+        assert_eq!(
+            add_copyright_with("#!/bin/sh", &placement).await,
+            "#!/bin/sh\n# Copyright 2020\n"
+        );
+        assert_eq!(
+            add_copyright_with("echo hi\n\n", &placement).await,
+            "# Copyright 2020\necho hi\n\n"
+        );
+        assert_eq!(
+            add_copyright_with("echo hi\r\necho ho", &placement).await,
+            "# Copyright 2020\r\necho hi\r\necho ho\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_copyright_added_below_other_holders() {
+        let dir = tempfile::tempdir().unwrap();
+        let filepath = dir.path().join("file");
+        std::fs::write(&filepath, "#!/bin/sh\n# Copyright 2019 Jane\necho hi\n").unwrap();
+
+        let regexes = CopyrightRegexes {
+            any_holder: Some(Arc::new(
+                Regex::new(r"^# Copyright (?P<years>\d{4}) .+?$").unwrap(),
+            )),
+            ..CopyrightRegexes::new(Arc::new(
+                Regex::new(r"^# Copyright (?P<years>\d{4}) Org$").unwrap(),
+            ))
+        };
+        let status = read_write_copyright(
+            filepath.clone(),
+            &regexes,
+            ready("2020".into()),
+            ready("# Copyright 2020 Org".into()),
+            &Placement {
+                first_line_prefixes: vec!["#!"],
+                ..Default::default()
+            },
+            Changes::default(),
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(status, FileStatus::Added { .. }));
+        assert_eq!(
+            std::fs::read_to_string(filepath).unwrap(),
+            "#!/bin/sh\n# Copyright 2019 Jane\n# Copyright 2020 Org\necho hi\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rewrite_from_scanned_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let filepath = dir.path().join("file");
+        // The file on disk differs from the content scanned, e.g. since it
+        // changed in between, only the scanned content must be rewritten
+        std::fs::write(&filepath, "# Copyright 2019\n#!/bin/sh\necho ho\n").unwrap();
+        let permissions = std::fs::metadata(&filepath).unwrap().permissions();
+        let mut reader = Cursor::new(b"#!/bin/sh\n# Copyright 2019\necho hi\n".to_vec());
+
+        let regex = Arc::new(Regex::new(r"^# Copyright (?P<years>\d{4})$").unwrap());
+        let scan = scan_header(
+            &filepath,
+            &mut reader,
+            &CopyrightRegexes::new(regex),
+            "2020".into(),
+            "# Copyright 2020",
+        )
+        .await
+        .unwrap();
+        assert_eq!(scan.edit, Edit::Replace(1));
+        assert_eq!(scan.header, b"#!/bin/sh\n# Copyright 2019\n");
+
+        write_copyright(
+            &filepath,
+            Cursor::new(scan.header).chain(reader),
+            permissions,
+            "# Copyright 2020",
+            scan.edit,
+            &Placement::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(filepath).unwrap(),
+            "#!/bin/sh\n# Copyright 2020\necho hi\n"
+        );
+    }
 }
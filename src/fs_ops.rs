@@ -0,0 +1,122 @@
+//! Extract files and modification times from a plain directory without git.
+
+use crate::CError;
+use chrono::{DateTime, FixedOffset, Utc};
+use std::path::Path;
+
+/// Get the files below `root` relative to it with `/` as separator, sorted.
+///
+/// `.git` directories and symlinks are skipped.
+pub fn get_files_in_dir(root: &Path) -> Result<Vec<String>, CError> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let path = entry.path();
+            if file_type.is_dir() && entry.file_name() != ".git" {
+                dirs.push(path);
+            } else if file_type.is_file() {
+                let relative = path.strip_prefix(root).unwrap_or(&path);
+                let components: Vec<_> = relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect();
+                files.push(components.join("/"));
+            }
+        }
+    }
+    files.sort_unstable();
+    Ok(files)
+}
+
+/// Get the names of the other files in the directory of `path`, sorted.
+pub fn get_sibling_files(path: &Path) -> Result<Vec<String>, CError> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut siblings = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() && Some(entry.file_name().as_os_str()) != path.file_name() {
+            siblings.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    siblings.sort_unstable();
+    Ok(siblings)
+}
+
+/// Get the modification time of a file as the only date of its history.
+///
+/// Creation times are not used since they usually are the time a file was
+/// extracted or copied rather than the time it was written.
+pub fn get_modification_dates_for_file(path: &Path) -> Vec<DateTime<FixedOffset>> {
+    match std::fs::metadata(path).and_then(|metadata| metadata.modified()) {
+        Ok(modified) => vec![DateTime::<Utc>::from(modified).fixed_offset()],
+        Err(e) => {
+            log::warn!(
+                "Could not get the modification time of {}: {}",
+                path.display(),
+                e
+            );
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{get_files_in_dir, get_modification_dates_for_file, get_sibling_files};
+    use crate::test_utils::write_file;
+    use chrono::{DateTime, Datelike};
+    use std::time::SystemTime;
+
+    #[test]
+    fn test_get_files_in_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        for filepath in ["b.py", "src/a.rs", "src/nested/c.sh", ".git/config"] {
+            write_file(dir.path(), filepath, "");
+        }
+
+        assert_eq!(
+            get_files_in_dir(dir.path()).unwrap(),
+            vec!["b.py", "src/a.rs", "src/nested/c.sh"]
+        );
+    }
+
+    #[test]
+    fn test_get_sibling_files() {
+        let dir = tempfile::tempdir().unwrap();
+        for filepath in ["b.py", "a.py", "run", "sub/c.py"] {
+            write_file(dir.path(), filepath, "");
+        }
+
+        assert_eq!(
+            get_sibling_files(&dir.path().join("run")).unwrap(),
+            vec!["a.py", "b.py"]
+        );
+    }
+
+    #[test]
+    fn test_get_modification_dates_for_file() {
+        let dir = tempfile::tempdir().unwrap();
+        write_file(dir.path(), "a.py", "");
+        let modified: SystemTime = DateTime::parse_from_rfc3339("2019-05-01T10:00:00+00:00")
+            .unwrap()
+            .into();
+        std::fs::File::options()
+            .write(true)
+            .open(dir.path().join("a.py"))
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+
+        let dates = get_modification_dates_for_file(&dir.path().join("a.py"));
+        assert_eq!(dates.len(), 1);
+        assert_eq!(dates[0].year(), 2019);
+        assert!(get_modification_dates_for_file(&dir.path().join("missing.py")).is_empty());
+    }
+}
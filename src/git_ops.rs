@@ -2,78 +2,608 @@
 //!
 
 use crate::CError;
-use chrono::Utc;
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Utc};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use tokio::process::Command;
 
-pub async fn get_files_on_ref(repo_path: &str, ref_name: &str) -> Result<Vec<String>, CError> {
-    let output = Command::new("git")
+/// Git directory and work tree passed to all git commands, for checkouts in
+/// which git does not find them from the working directory, e.g. a bare
+/// repository with a separate work tree.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GitDirs {
+    /// Passed as `--git-dir`
+    pub git_dir: Option<PathBuf>,
+    /// Passed as `--work-tree`
+    pub work_tree: Option<PathBuf>,
+}
+
+impl GitDirs {
+    /// Create a git command run in `cwd`.
+    fn command(&self, cwd: &str) -> Command {
+        let mut command = Command::new("git");
+        if let Some(git_dir) = self.git_dir.as_ref() {
+            command.arg("--git-dir").arg(git_dir);
+        }
+        if let Some(work_tree) = self.work_tree.as_ref() {
+            command.arg("--work-tree").arg(work_tree);
+        }
+        command.current_dir(cwd);
+        command
+    }
+}
+
+pub async fn get_files_on_ref(
+    repo_path: &str,
+    git_dirs: &GitDirs,
+    ref_name: &str,
+) -> Result<Vec<String>, CError> {
+    let output = git_dirs
+        .command(repo_path)
         .arg("ls-tree")
         .arg("-r")
         .arg(ref_name)
         .arg("--name-only")
-        .current_dir(repo_path)
         .output();
 
-    let output = output.await?;
-    if !output.status.success() {
-        return Err(CError::GitCmdError(
-            String::from_utf8(output.stderr).map_err(|e| e.utf8_error())?,
-        ));
+    parse_cmd_output("ls-tree", &output.await?)
+}
+
+/// Get the sizes in bytes of the files tracked on `ref_name`.
+///
+/// The sizes are those of the committed blobs, which can differ from the
+/// files in the work tree, e.g. for generated files. Submodules have no size
+/// and are left out.
+pub async fn get_blob_sizes(
+    repo_path: &str,
+    git_dirs: &GitDirs,
+    ref_name: &str,
+) -> Result<HashMap<String, u64>, CError> {
+    let output = git_dirs
+        .command(repo_path)
+        .arg("ls-tree")
+        .arg("-r")
+        .arg("-l")
+        .arg(ref_name)
+        .output();
+
+    Ok(parse_cmd_output("ls-tree", &output.await?)?
+        .iter()
+        .filter_map(|line| {
+            // <mode> <type> <object> <size>\t<path>
+            let (info, path) = line.split_once('\t')?;
+            let size = info.split_whitespace().nth(3)?.parse().ok()?;
+            Some((path.to_owned(), size))
+        })
+        .collect())
+}
+
+/// Get files changed on `HEAD` since its merge base with `since_ref`.
+///
+/// Deleted files are left out since they cannot get a copyright.
+pub async fn get_files_changed_since(
+    repo_path: &str,
+    git_dirs: &GitDirs,
+    since_ref: &str,
+) -> Result<Vec<String>, CError> {
+    let output = git_dirs
+        .command(repo_path)
+        .arg("diff")
+        .arg("--name-only")
+        .arg("--diff-filter=d")
+        .arg(format!("{}...HEAD", since_ref))
+        .output();
+
+    parse_cmd_output("diff", &output.await?)
+}
+
+/// Get the paths of the initialized submodules of a repository relative to
+/// it, including nested submodules.
+pub async fn get_submodules(repo_path: &str, git_dirs: &GitDirs) -> Result<Vec<String>, CError> {
+    let output = git_dirs
+        .command(repo_path)
+        .arg("submodule")
+        .arg("status")
+        .arg("--recursive")
+        .output();
+
+    Ok(parse_cmd_output("submodule", &output.await?)?
+        .iter()
+        .filter_map(|line| parse_submodule_status(line))
+        .collect())
+}
+
+/// Parse the path of an initialized submodule from a line of `git submodule
+/// status`, which is `<state><sha> <path>` with an optional ` (<describe>)`.
+fn parse_submodule_status(line: &str) -> Option<String> {
+    if line.starts_with('-') {
+        log::info!("Skipping uninitialized submodule {}", line);
+        return None;
     }
+    let (_, path) = line.get(1..)?.split_once(' ')?;
+    let path = match path.ends_with(')') {
+        true => path.rsplit_once(" (").map_or(path, |(path, _)| path),
+        false => path,
+    };
+    Some(path.to_owned())
+}
 
-    Ok(parse_cmd_output(&output)?)
+/// Get the root directory of the repository containing `path`.
+pub async fn get_toplevel(path: &str, git_dirs: &GitDirs) -> Result<String, CError> {
+    let output = git_dirs
+        .command(path)
+        .arg("rev-parse")
+        .arg("--show-toplevel")
+        .output();
+
+    parse_cmd_output("rev-parse", &output.await?)?
+        .pop()
+        .ok_or_else(|| CError::GitCmdError {
+            command: "rev-parse",
+            code: Some(0),
+            stderr: "no toplevel returned".into(),
+        })
+}
+
+/// Get the absolute path of the git directory of the repository containing `path`.
+pub async fn get_git_dir(path: &str, git_dirs: &GitDirs) -> Result<PathBuf, CError> {
+    let output = git_dirs
+        .command(path)
+        .arg("rev-parse")
+        .arg("--absolute-git-dir")
+        .output();
+
+    parse_cmd_output("rev-parse", &output.await?)?
+        .pop()
+        .map(PathBuf::from)
+        .ok_or_else(|| CError::GitCmdError {
+            command: "rev-parse",
+            code: Some(0),
+            stderr: "no git directory returned".into(),
+        })
 }
 
 pub async fn get_added_mod_times_for_file(filepath: &str, cwd: &str) -> String {
-    let output = Command::new("git")
+    let commit_dates = get_commit_dates_for_file(filepath, cwd, &GitDirs::default(), &[]).await;
+    years_from_commit_dates(filepath, &commit_dates)
+}
+
+/// Get the commit dates of a file, usually latest first.
+///
+/// Commits with a hash starting with one of `ignore_revs` are left out, e.g.
+/// bulk formatting commits. If all commits of a file are ignored, they are
+/// used nevertheless.
+///
+/// For histories with merges, the dates of the commits on merged branches
+/// count, not the date of the merge. A merge only counts itself if it changed
+/// the file compared to all of its parents, e.g. to resolve a conflict. This
+/// is why neither `-m`, which lists merges once per parent, nor
+/// `--first-parent`, which attributes all work on a branch to its merge, is
+/// used. A file squash-merged from a branch only has the squash commit.
+pub async fn get_commit_dates_for_file(
+    filepath: &str,
+    cwd: &str,
+    git_dirs: &GitDirs,
+    ignore_revs: &[String],
+) -> Vec<DateTime<FixedOffset>> {
+    // Records start with NUL to not depend on the layout of the output. With
+    // `--follow`, merges are only listed with a diff format for merges. The
+    // dense combined diff lists the file only for merges which changed it
+    // compared to all parents.
+    let output = git_dirs
+        .command(cwd)
         .arg("log")
         .arg("--follow")
-        .arg("-m")
-        .arg("--pretty=%ci")
+        .arg("--cc")
+        .arg("--name-only")
+        .arg("--pretty=format:%x00%H %ci")
         .arg(filepath)
-        .current_dir(cwd)
         .output();
     let output = output.await.unwrap().stdout;
-    let commit_years: Vec<String> = std::str::from_utf8(&output)
+    let mut seen = HashSet::new();
+    let commits: Vec<(&str, DateTime<FixedOffset>)> = std::str::from_utf8(&output)
         .unwrap()
-        .split('\n')
-        .filter_map(|s| {
-            // Take only first four chars (the year) from strings that are longer than zero
-            let s = s.to_owned();
-            match s.len() {
-                0 => None,
-                _ => Some(s.chars().take(4).collect()),
+        .split('\0')
+        .filter_map(|record| {
+            let mut lines = record.lines();
+            let s = lines.next()?.trim();
+            let (hash, date) = s.split_once(' ')?;
+            if !lines.any(|line| !line.trim().is_empty()) {
+                log::debug!("Merge {} did not change {}", hash, filepath);
+                return None;
+            }
+            if !seen.insert(hash) {
+                return None;
             }
+            Some((hash, parse_commit_date(date, filepath)?))
         })
         .collect();
+    without_ignored_revs(filepath, commits, ignore_revs)
+}
 
-    match commit_years.len() {
-        0 => {
-            log::debug!("File {} is untracked, add current year", filepath);
-            Utc::now().date().format("%Y").to_string()
-        }
-        1 => {
-            log::debug!("File {} was only committed once", filepath);
-            commit_years[0].clone()
-        }
-        num_commits => {
-            log::debug!("File {} was modified {} times", filepath, num_commits);
-            let added = commit_years[commit_years.len() - 1].clone();
-            let last_modified = commit_years[0].clone();
-            match added == last_modified {
-                true => added,
-                false => format!("{}-{}", added, last_modified),
+/// Get the commit dates of many files with one `git log` per batch of
+/// `batch_size` files.
+///
+/// This spawns far fewer processes than `get_commit_dates_for_file` while
+/// bounding the size of each output. Since `--follow` only works for a single
+/// file, the history of a file before it was renamed is not included. Merges
+/// and `ignore_revs` are treated like for a single file. Every file of
+/// `filepaths` has an entry, which is empty for a file without commits.
+pub async fn get_commit_dates_for_files(
+    filepaths: &[&str],
+    cwd: &str,
+    git_dirs: &GitDirs,
+    ignore_revs: &[String],
+    batch_size: usize,
+) -> Result<HashMap<String, Vec<DateTime<FixedOffset>>>, CError> {
+    let mut commit_dates = HashMap::with_capacity(filepaths.len());
+    for batch in filepaths.chunks(batch_size.max(1)) {
+        // With `-z`, the names of the files changed by a commit are separated
+        // by NUL, so records start with another control character. The header
+        // of a merge also ends with NUL instead of a newline.
+        let output = git_dirs
+            .command(cwd)
+            .arg("--literal-pathspecs")
+            .arg("log")
+            .arg("-z")
+            .arg("--cc")
+            .arg("--name-only")
+            .arg("--relative")
+            .arg("--pretty=format:%x01%H %ci")
+            .arg("--")
+            .args(batch)
+            .output();
+        let output = output.await?;
+        if !output.status.success() {
+            return Err(CError::GitCmdError {
+                command: "log",
+                code: output.status.code(),
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+            });
+        }
+        let output = std::str::from_utf8(&output.stdout)?;
+        let mut commits: HashMap<&str, Vec<(&str, DateTime<FixedOffset>)>> = batch
+            .iter()
+            .map(|filepath| (*filepath, Vec::new()))
+            .collect();
+        for record in output.split('\x01') {
+            let (header, names) = record.split_once(['\n', '\0']).unwrap_or((record, ""));
+            let (hash, date) = match header.trim().split_once(' ') {
+                Some(header) => header,
+                None => continue,
+            };
+            for name in names.split('\0') {
+                if let Some(file_commits) = commits.get_mut(name) {
+                    if file_commits.iter().all(|(seen, _)| *seen != hash) {
+                        if let Some(date) = parse_commit_date(date, name) {
+                            file_commits.push((hash, date));
+                        }
+                    }
+                }
             }
         }
+        for (filepath, file_commits) in commits {
+            commit_dates.insert(
+                filepath.to_owned(),
+                without_ignored_revs(filepath, file_commits, ignore_revs),
+            );
+        }
+    }
+    Ok(commit_dates)
+}
+
+fn parse_commit_date(date: &str, filepath: &str) -> Option<DateTime<FixedOffset>> {
+    match DateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S %z") {
+        Ok(date) => Some(date),
+        Err(e) => {
+            log::warn!(
+                "Could not parse commit date {} of {}: {}",
+                date,
+                filepath,
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Leave out commits with a hash starting with one of `ignore_revs`, unless
+/// all commits would be left out.
+fn without_ignored_revs(
+    filepath: &str,
+    commits: Vec<(&str, DateTime<FixedOffset>)>,
+    ignore_revs: &[String],
+) -> Vec<DateTime<FixedOffset>> {
+    let considered: Vec<DateTime<FixedOffset>> = commits
+        .iter()
+        .filter(|(hash, _)| {
+            let ignored = ignore_revs.iter().any(|rev| hash.starts_with(rev.as_str()));
+            if ignored {
+                log::debug!("Ignoring commit {} for {}", hash, filepath);
+            }
+            !ignored
+        })
+        .map(|(_, date)| *date)
+        .collect();
+
+    match considered.is_empty() && !commits.is_empty() {
+        true => {
+            log::warn!("All commits of {} are ignored, using them anyway", filepath);
+            commits.into_iter().map(|(_, date)| date).collect()
+        }
+        false => considered,
+    }
+}
+
+/// Get the date of the first commit adding a file, following renames.
+///
+/// Unlike the oldest date of `get_commit_dates_for_file`, this does not depend
+/// on the dates of the other commits, which may be skewed in rewritten history.
+pub async fn get_add_date_for_file(
+    filepath: &str,
+    cwd: &str,
+    git_dirs: &GitDirs,
+) -> Option<DateTime<FixedOffset>> {
+    let output = git_dirs
+        .command(cwd)
+        .arg("log")
+        .arg("--follow")
+        .arg("--diff-filter=A")
+        .arg("--reverse")
+        .arg("--pretty=format:%ci")
+        .arg(filepath)
+        .output();
+    let output = output.await.ok()?.stdout;
+    let first_line = std::str::from_utf8(&output).ok()?.lines().next()?;
+    match DateTime::parse_from_str(first_line.trim(), "%Y-%m-%d %H:%M:%S %z") {
+        Ok(date) => Some(date),
+        Err(e) => {
+            log::warn!(
+                "Could not parse add date {} of {}: {}",
+                first_line,
+                filepath,
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Make `added` the oldest commit date, dropping dates before it.
+pub fn with_added_date(
+    mut commit_dates: Vec<DateTime<FixedOffset>>,
+    added: DateTime<FixedOffset>,
+) -> Vec<DateTime<FixedOffset>> {
+    commit_dates.retain(|date| *date >= added);
+    if !commit_dates.contains(&added) {
+        commit_dates.push(added);
+    }
+    commit_dates
+}
+
+/// Read commit hashes from a file in the format of `git blame --ignore-revs-file`.
+///
+/// Each line holds one hash, blank lines and comments starting with `#` are skipped.
+pub fn read_ignore_revs_file(path: &Path) -> Result<Vec<String>, CError> {
+    let content =
+        std::fs::read_to_string(path).map_err(|_| CError::ReadError(path.display().to_string()))?;
+    Ok(content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_owned())
+        .collect())
+}
+
+/// Bounds of the years from the history, e.g. for the first rollout of
+/// copyrights in a legacy repository.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct YearBounds {
+    /// Earlier years are raised to this year, e.g. the founding year of a project
+    pub start_floor: Option<i32>,
+    /// End ranges with the current year instead of the year of the last change
+    pub until_current_year: bool,
+}
+
+impl YearBounds {
+    /// Apply the bounds to years rendered by `years_from_commit_dates`, the
+    /// floor first. Years which cannot be parsed are returned as they are.
+    pub fn apply(&self, years: &str) -> String {
+        let (start, end) = years.split_once('-').unwrap_or((years, years));
+        let (start, end) = match (start.parse::<i32>(), end.parse::<i32>()) {
+            (Ok(start), Ok(end)) => (start, end),
+            _ => return years.to_owned(),
+        };
+        let start = start.max(self.start_floor.unwrap_or(start));
+        let end = match self.until_current_year {
+            true => now().year(),
+            false => end,
+        }
+        .max(start);
+        match start == end {
+            true => start.to_string(),
+            false => format!("{}-{}", start, end),
+        }
+    }
+}
+
+/// How years are written in copyrights.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum YearFormat {
+    /// Four digits, e.g. `2019-2021`
+    #[default]
+    FourDigit,
+    /// Two digits after an apostrophe as in legacy notices, e.g. `'19-'21`
+    TwoDigit,
+}
+
+impl YearFormat {
+    /// Render four-digit years like `2019-2021` or lists of them like
+    /// `2019, 2021-2022` in this format.
+    pub fn format(&self, years: &str) -> String {
+        match self {
+            YearFormat::FourDigit => years.to_owned(),
+            YearFormat::TwoDigit => years
+                .split(", ")
+                .map(|range| {
+                    range
+                        .split('-')
+                        .map(|year| format!("'{}", &year[year.len().saturating_sub(2)..]))
+                        .collect::<Vec<_>>()
+                        .join("-")
+                })
+                .collect::<Vec<_>>()
+                .join(", "),
+        }
+    }
+
+    /// Regex of a year or range in this format.
+    pub fn regex(&self) -> &'static str {
+        match self {
+            YearFormat::FourDigit => r"(?P<years>\d{4}(-\d{4}){0,1})",
+            YearFormat::TwoDigit => r"(?P<years>'\d{2}(-'\d{2}){0,1})",
+        }
+    }
+
+    /// Regex of a list of years and ranges in this format, e.g. `2019, 2021-2022`.
+    pub fn list_regex(&self) -> &'static str {
+        match self {
+            YearFormat::FourDigit => r"(?P<years>\d{4}(-\d{4})?(, \d{4}(-\d{4})?)*)",
+            YearFormat::TwoDigit => r"(?P<years>'\d{2}(-'\d{2})?(, '\d{2}(-'\d{2})?)*)",
+        }
+    }
+}
+
+/// Render the years of the commit dates as a range from the oldest to the
+/// newest year, a single year if they are the same. The range is independent
+/// of the order and number of the dates and does not repeat years, so the
+/// years of a file only change with commits in another year.
+pub fn years_from_commit_dates(filepath: &str, commit_dates: &[DateTime<FixedOffset>]) -> String {
+    // Do not rely on the order of git log, which may differ with merges
+    let (added, last_modified) = match (commit_dates.iter().min(), commit_dates.iter().max()) {
+        (Some(added), Some(last_modified)) => (
+            added.format("%Y").to_string(),
+            last_modified.format("%Y").to_string(),
+        ),
+        _ => {
+            log::debug!("File {} is untracked, add current year", filepath);
+            return now().date_naive().format("%Y").to_string();
+        }
+    };
+    log::debug!(
+        "File {} was committed {} times",
+        filepath,
+        commit_dates.len()
+    );
+    match added == last_modified {
+        true => added,
+        false => format!("{}-{}", added, last_modified),
+    }
+}
+
+/// Render the years of the commit dates as a list of every year with a
+/// commit, see `normalize_years`.
+pub fn year_list_from_commit_dates(
+    filepath: &str,
+    commit_dates: &[DateTime<FixedOffset>],
+    collapse_runs: bool,
+) -> String {
+    if commit_dates.is_empty() {
+        log::debug!("File {} is untracked, add current year", filepath);
+        return current_year();
+    }
+    normalize_years(commit_dates.iter().map(|date| date.year()), collapse_runs)
+}
+
+/// Render `years` sorted and without duplicates as a list separated by `, `,
+/// e.g. `2019, 2021, 2022`. If `collapse_runs` is set, runs of consecutive
+/// years are collapsed into ranges, e.g. `2019, 2021-2022`.
+pub fn normalize_years(years: impl IntoIterator<Item = i32>, collapse_runs: bool) -> String {
+    let years: BTreeSet<i32> = years.into_iter().collect();
+    let mut runs: Vec<(i32, i32)> = Vec::new();
+    for year in years {
+        match runs.last_mut() {
+            Some((_, end)) if collapse_runs && *end + 1 == year => *end = year,
+            _ => runs.push((year, year)),
+        }
+    }
+    runs.iter()
+        .map(|&(start, end)| match start == end {
+            true => start.to_string(),
+            false => format!("{}-{}", start, end),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Environment variable with a fixed time in seconds since the epoch, see
+/// <https://reproducible-builds.org/specs/source-date-epoch/>.
+const SOURCE_DATE_EPOCH: &str = "SOURCE_DATE_EPOCH";
+
+/// Current time, the one of `SOURCE_DATE_EPOCH` if set for reproducible builds.
+fn now() -> DateTime<Utc> {
+    source_date(std::env::var(SOURCE_DATE_EPOCH).ok().as_deref()).unwrap_or_else(Utc::now)
+}
+
+/// Current year, e.g. for templates with `{current_year}`.
+pub fn current_year() -> String {
+    now().format("%Y").to_string()
+}
+
+/// Parse the value of `SOURCE_DATE_EPOCH`, invalid values are ignored.
+fn source_date(epoch: Option<&str>) -> Option<DateTime<Utc>> {
+    let epoch = epoch?;
+    let date = epoch
+        .trim()
+        .parse::<i64>()
+        .ok()
+        .and_then(|secs| DateTime::from_timestamp(secs, 0));
+    if date.is_none() {
+        log::warn!("Ignoring invalid {} {}", SOURCE_DATE_EPOCH, epoch);
     }
+    date
 }
 
-pub async fn check_for_changes(repo_path: &str, fail_on_diff: bool) -> Result<(), CError> {
-    let diff_files = get_diffs(repo_path).await?;
-    if diff_files.len() > 0 {
-        println!("Files changed:");
+/// Oldest year which is plausible in a copyright, older ones hint at dates
+/// which were not set, e.g. the start of the Unix epoch.
+const FIRST_PLAUSIBLE_YEAR: i32 = 1970;
+
+/// Check if all years of a year or range are between 1970 and next year.
+///
+/// Other years hint at broken dates in the history, e.g. commits made with a
+/// wrong system clock.
+pub fn are_plausible_years(years: &str) -> bool {
+    let last_plausible_year = now().year() + 1;
+    years
+        .split(", ")
+        .flat_map(|range| range.split('-'))
+        .all(|year| {
+            year.parse::<i32>()
+                .is_ok_and(|year| (FIRST_PLAUSIBLE_YEAR..=last_plausible_year).contains(&year))
+        })
+}
+
+/// Check if a file was added less than `days` before `now()`, untracked files
+/// count as new.
+pub fn is_younger_than(commit_dates: &[DateTime<FixedOffset>], days: u32) -> bool {
+    match commit_dates.iter().min() {
+        Some(added) => now().signed_duration_since(*added) < Duration::days(days.into()),
+        None => true,
+    }
+}
+
+pub async fn check_for_changes(
+    repo_path: &str,
+    git_dirs: &GitDirs,
+    fail_on_diff: bool,
+    report: &mut dyn Write,
+) -> Result<(), CError> {
+    let diff_files = get_diffs(repo_path, git_dirs).await?;
+    if !diff_files.is_empty() {
+        writeln!(report, "Files changed:")?;
         for filepath in diff_files.iter() {
-            println!("{}", filepath);
+            writeln!(report, "{}", filepath)?;
         }
 
         if fail_on_diff {
@@ -84,24 +614,29 @@ pub async fn check_for_changes(repo_path: &str, fail_on_diff: bool) -> Result<()
     Ok(())
 }
 
-async fn get_diffs<'a>(repo_path: &str) -> Result<Vec<String>, CError> {
-    let output = Command::new("git")
+async fn get_diffs(repo_path: &str, git_dirs: &GitDirs) -> Result<Vec<String>, CError> {
+    let output = git_dirs
+        .command(repo_path)
         .arg("diff")
         .arg("--name-only")
-        .current_dir(repo_path)
         .output();
 
-    let output = output.await?;
-    if !output.status.success() {
-        return Err(CError::GitCmdError(
-            String::from_utf8(output.stderr).map_err(|e| e.utf8_error())?,
-        ));
-    }
-
-    Ok(parse_cmd_output(&output)?)
+    parse_cmd_output("diff", &output.await?)
 }
 
-fn parse_cmd_output(output: &std::process::Output) -> Result<Vec<String>, CError> {
+/// Get the lines of the output of the git subcommand `command`, which is
+/// named in the error if it failed.
+fn parse_cmd_output(
+    command: &'static str,
+    output: &std::process::Output,
+) -> Result<Vec<String>, CError> {
+    if !output.status.success() {
+        return Err(CError::GitCmdError {
+            command,
+            code: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        });
+    }
     let output = std::str::from_utf8(&output.stdout)?;
     let lines: Vec<String> = output
         .split('\n')
@@ -115,3 +650,546 @@ fn parse_cmd_output(output: &std::process::Output) -> Result<Vec<String>, CError
         .collect();
     Ok(lines)
 }
+
+#[cfg(test)]
+mod test {
+
+    use super::get_commit_dates_for_files;
+    use super::{are_plausible_years, is_younger_than, now, source_date, years_from_commit_dates};
+    use super::{get_add_date_for_file, with_added_date};
+    use super::{get_blob_sizes, get_files_on_ref, get_toplevel, parse_submodule_status, GitDirs};
+    use super::{get_commit_dates_for_file, get_files_changed_since, read_ignore_revs_file};
+    use super::{normalize_years, year_list_from_commit_dates, YearBounds, YearFormat};
+    use crate::test_utils::{create_repo, git_at, head_sha, repo_str, write_file};
+    use crate::CError;
+    use chrono::{DateTime, Datelike, Duration, Utc};
+
+    #[test]
+    fn test_source_date() {
+        // The variable itself is not set since tests run in parallel
+        assert_eq!(
+            source_date(Some("1609459200")).map(|date| date.to_rfc3339()),
+            Some("2021-01-01T00:00:00+00:00".into())
+        );
+        assert_eq!(source_date(Some("not a number")), None);
+        assert_eq!(source_date(None), None);
+    }
+
+    #[test]
+    fn test_years_from_commit_dates() {
+        let dates: Vec<_> = ["2022-03-01 10:00:00 +0100", "2019-05-01 10:00:00 +0200"]
+            .iter()
+            .map(|d| DateTime::parse_from_str(d, "%Y-%m-%d %H:%M:%S %z").unwrap())
+            .collect();
+        assert_eq!(years_from_commit_dates("file.rs", &dates), "2019-2022");
+        assert_eq!(years_from_commit_dates("file.rs", &dates[1..]), "2019");
+    }
+
+    #[test]
+    fn test_are_plausible_years() {
+        let next_year = (Utc::now().year() + 1).to_string();
+        assert!(are_plausible_years("2020"));
+        assert!(are_plausible_years("1970-2020"));
+        assert!(are_plausible_years(&next_year));
+        assert!(!are_plausible_years("0000"));
+        assert!(!are_plausible_years("2019-9999"));
+        assert!(!are_plausible_years("1969-2020"));
+        assert!(!are_plausible_years("20x0"));
+    }
+
+    #[test]
+    fn test_year_bounds() {
+        let current_year = Utc::now().year();
+        let floor = YearBounds {
+            start_floor: Some(2018),
+            until_current_year: false,
+        };
+        assert_eq!(floor.apply("2015-2019"), "2018-2019");
+        assert_eq!(floor.apply("2015"), "2018");
+        assert_eq!(floor.apply("2019-2020"), "2019-2020");
+
+        let until_current_year = YearBounds {
+            start_floor: Some(2018),
+            until_current_year: true,
+        };
+        assert_eq!(
+            until_current_year.apply("2015-2019"),
+            format!("2018-{}", current_year)
+        );
+        assert_eq!(
+            until_current_year.apply(&current_year.to_string()),
+            current_year.to_string()
+        );
+        assert_eq!(until_current_year.apply("'19"), "'19");
+        assert_eq!(YearBounds::default().apply("2015-2019"), "2015-2019");
+    }
+
+    #[test]
+    fn test_year_format() {
+        assert_eq!(YearFormat::FourDigit.format("2019-2021"), "2019-2021");
+        assert_eq!(YearFormat::TwoDigit.format("2019-2021"), "'19-'21");
+        assert_eq!(YearFormat::TwoDigit.format("2005"), "'05");
+        assert_eq!(
+            YearFormat::TwoDigit.format("2015, 2019-2021"),
+            "'15, '19-'21"
+        );
+    }
+
+    #[test]
+    fn test_normalize_years() {
+        // Unsorted and duplicated years are sorted and deduplicated
+        assert_eq!(
+            normalize_years([2021, 2019, 2021, 2015], false),
+            "2015, 2019, 2021"
+        );
+        // Runs of adjacent years are only collapsed if enabled
+        let years = [2020, 2019, 2023, 2021, 2019, 2024];
+        assert_eq!(
+            normalize_years(years, false),
+            "2019, 2020, 2021, 2023, 2024"
+        );
+        assert_eq!(normalize_years(years, true), "2019-2021, 2023-2024");
+        assert_eq!(normalize_years([2019, 2019], true), "2019");
+        assert_eq!(normalize_years([2019, 2021], true), "2019, 2021");
+    }
+
+    #[test]
+    fn test_year_list_from_commit_dates() {
+        let dates: Vec<_> = [
+            "2022-09-01 10:00:00 +0100",
+            "2019-03-01 10:00:00 +0100",
+            "2022-01-01 10:00:00 +0100",
+            "2021-05-01 10:00:00 +0100",
+        ]
+        .iter()
+        .map(|d| DateTime::parse_from_str(d, "%Y-%m-%d %H:%M:%S %z").unwrap())
+        .collect();
+        assert_eq!(
+            year_list_from_commit_dates("file.rs", &dates, false),
+            "2019, 2021, 2022"
+        );
+        assert_eq!(
+            year_list_from_commit_dates("file.rs", &dates, true),
+            "2019, 2021-2022"
+        );
+    }
+
+    #[test]
+    fn test_is_younger_than() {
+        let recent = (now() - Duration::days(3)).fixed_offset();
+        let old = (now() - Duration::days(300)).fixed_offset();
+        assert!(is_younger_than(&[recent], 30));
+        assert!(!is_younger_than(&[recent, old], 30));
+        assert!(is_younger_than(&[], 30));
+    }
+
+    #[tokio::test]
+    async fn test_get_toplevel_from_nested_dir() {
+        let repo = create_repo(&[("a/b/file.rs", "fn main() {}\n")], "2020-06-01T12:00:00");
+        let nested = repo.path().join("a/b");
+
+        let toplevel = get_toplevel(nested.to_str().unwrap(), &GitDirs::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::canonicalize(toplevel).unwrap(),
+            std::fs::canonicalize(repo.path()).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bare_repo_with_work_tree() {
+        let repo = create_repo(&[("main.rs", "fn main() {}\n")], "2020-06-01T12:00:00");
+        let bare = tempfile::tempdir().unwrap();
+        let work_tree = tempfile::tempdir().unwrap();
+        let git_dirs = GitDirs {
+            git_dir: Some(bare.path().join("repo.git")),
+            work_tree: Some(work_tree.path().to_path_buf()),
+        };
+        git_at(
+            bare.path(),
+            &["clone", "-q", "--bare", repo_str(&repo), "repo.git"],
+            "2020-06-01T12:00:00",
+        );
+        let git_dir = bare.path().join("repo.git");
+        git_at(
+            work_tree.path(),
+            &[
+                "--git-dir",
+                git_dir.to_str().unwrap(),
+                "--work-tree",
+                ".",
+                "checkout",
+                "-f",
+            ],
+            "2020-06-01T12:00:00",
+        );
+
+        let files = get_files_on_ref(repo_str(&work_tree), &git_dirs, "HEAD")
+            .await
+            .unwrap();
+        assert_eq!(files, vec!["main.rs"]);
+        let dates =
+            get_commit_dates_for_file("main.rs", repo_str(&work_tree), &git_dirs, &[]).await;
+        assert_eq!(years_from_commit_dates("main.rs", &dates), "2020");
+    }
+
+    #[tokio::test]
+    async fn test_ignore_revs() {
+        let repo = create_repo(&[("main.rs", "fn main() {}\n")], "2020-06-01T12:00:00");
+        write_file(repo.path(), "main.rs", "fn main() {\n}\n");
+        git_at(
+            repo.path(),
+            &["commit", "-qam", "Reformat"],
+            "2022-06-01T12:00:00",
+        );
+        let reformat_sha = head_sha(repo.path());
+
+        let dates =
+            get_commit_dates_for_file("main.rs", repo_str(&repo), &GitDirs::default(), &[]).await;
+        assert_eq!(years_from_commit_dates("main.rs", &dates), "2020-2022");
+
+        let ignore_revs = [reformat_sha[..10].to_owned()];
+        let dates = get_commit_dates_for_file(
+            "main.rs",
+            repo_str(&repo),
+            &GitDirs::default(),
+            &ignore_revs,
+        )
+        .await;
+        assert_eq!(years_from_commit_dates("main.rs", &dates), "2020");
+    }
+
+    #[tokio::test]
+    async fn test_get_blob_sizes() {
+        let repo = create_repo(
+            &[("a.rs", "fn a() {}\n"), ("src/b.rs", "")],
+            "2020-06-01T12:00:00",
+        );
+        write_file(repo.path(), "a.rs", "fn a() {\n    // changed\n}\n");
+
+        let sizes = get_blob_sizes(repo_str(&repo), &GitDirs::default(), "HEAD")
+            .await
+            .unwrap();
+        assert_eq!(sizes.len(), 2);
+        assert_eq!(sizes["a.rs"], 10);
+        assert_eq!(sizes["src/b.rs"], 0);
+    }
+
+    #[test]
+    fn test_parse_submodule_status() {
+        let sha = "a".repeat(40);
+        assert_eq!(
+            parse_submodule_status(&format!(" {} libs/a (heads/main)", sha)),
+            Some("libs/a".into())
+        );
+        assert_eq!(
+            parse_submodule_status(&format!("+{} libs/with space", sha)),
+            Some("libs/with space".into())
+        );
+        assert_eq!(parse_submodule_status(&format!("-{} libs/b", sha)), None);
+    }
+
+    #[tokio::test]
+    async fn test_get_files_changed_since() {
+        let repo = create_repo(
+            &[("a.rs", "fn a() {}\n"), ("b.rs", "fn b() {}\n")],
+            "2020-06-01T12:00:00",
+        );
+        let base_sha = head_sha(repo.path());
+        write_file(repo.path(), "b.rs", "fn b() {\n}\n");
+        write_file(repo.path(), "c/d.rs", "fn d() {}\n");
+        std::fs::remove_file(repo.path().join("a.rs")).unwrap();
+        git_at(repo.path(), &["add", "-A"], "2021-06-01T12:00:00");
+        git_at(
+            repo.path(),
+            &["commit", "-q", "-m", "Change"],
+            "2021-06-01T12:00:00",
+        );
+
+        let files = get_files_changed_since(repo_str(&repo), &GitDirs::default(), &base_sha)
+            .await
+            .unwrap();
+        assert_eq!(files, vec!["b.rs", "c/d.rs"]);
+
+        let err = get_files_changed_since(repo_str(&repo), &GitDirs::default(), "no-such-ref")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().starts_with("git diff exited 128: "));
+    }
+
+    #[tokio::test]
+    async fn test_git_error_names_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = get_files_on_ref(repo_str(&dir), &GitDirs::default(), "HEAD")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            CError::GitCmdError {
+                command: "ls-tree",
+                code: Some(128),
+                ..
+            }
+        ));
+        assert!(err.to_string().contains("not a git repository"));
+    }
+
+    #[tokio::test]
+    async fn test_commit_dates_with_merge() {
+        let repo = create_repo(&[("main.rs", "fn main() {}\n")], "2019-06-01T12:00:00");
+        let date_feature = "2020-06-01T12:00:00";
+        git_at(
+            repo.path(),
+            &["checkout", "-q", "-b", "feature"],
+            date_feature,
+        );
+        write_file(repo.path(), "main.rs", "fn main() {\n}\n");
+        git_at(repo.path(), &["commit", "-qam", "Change"], date_feature);
+
+        let date_other = "2021-06-01T12:00:00";
+        git_at(repo.path(), &["checkout", "-q", "-"], date_other);
+        write_file(repo.path(), "other.rs", "fn other() {}\n");
+        git_at(repo.path(), &["add", "other.rs"], date_other);
+        git_at(repo.path(), &["commit", "-qm", "Other"], date_other);
+
+        let date_merge = "2022-06-01T12:00:00";
+        git_at(
+            repo.path(),
+            &["merge", "-q", "--no-ff", "-m", "Merge", "feature"],
+            date_merge,
+        );
+
+        let dates =
+            get_commit_dates_for_file("main.rs", repo_str(&repo), &GitDirs::default(), &[]).await;
+        assert_eq!(dates.len(), 2);
+        assert_eq!(years_from_commit_dates("main.rs", &dates), "2019-2020");
+        let dates =
+            get_commit_dates_for_file("other.rs", repo_str(&repo), &GitDirs::default(), &[]).await;
+        assert_eq!(years_from_commit_dates("other.rs", &dates), "2021");
+    }
+
+    #[tokio::test]
+    async fn test_commit_dates_with_changing_merge() {
+        let repo = create_repo(&[("main.rs", "fn main() {}\n")], "2019-06-01T12:00:00");
+        let date_feature = "2020-06-01T12:00:00";
+        git_at(
+            repo.path(),
+            &["checkout", "-q", "-b", "feature"],
+            date_feature,
+        );
+        write_file(repo.path(), "feature.rs", "fn feature() {}\n");
+        git_at(repo.path(), &["add", "feature.rs"], date_feature);
+        git_at(repo.path(), &["commit", "-qm", "Feature"], date_feature);
+
+        // The merge changes main.rs compared to both parents
+        let date_merge = "2023-06-01T12:00:00";
+        git_at(repo.path(), &["checkout", "-q", "-"], date_merge);
+        git_at(
+            repo.path(),
+            &["merge", "-q", "--no-ff", "--no-commit", "feature"],
+            date_merge,
+        );
+        write_file(repo.path(), "main.rs", "fn main() {\n}\n");
+        git_at(repo.path(), &["commit", "-qam", "Merge"], date_merge);
+
+        let dates =
+            get_commit_dates_for_file("main.rs", repo_str(&repo), &GitDirs::default(), &[]).await;
+        assert_eq!(years_from_commit_dates("main.rs", &dates), "2019-2023");
+        let dates =
+            get_commit_dates_for_file("feature.rs", repo_str(&repo), &GitDirs::default(), &[])
+                .await;
+        assert_eq!(years_from_commit_dates("feature.rs", &dates), "2020");
+    }
+
+    #[tokio::test]
+    async fn test_commit_dates_with_squash_merge() {
+        let repo = create_repo(&[("main.rs", "fn main() {}\n")], "2019-06-01T12:00:00");
+        git_at(
+            repo.path(),
+            &["checkout", "-q", "-b", "feature"],
+            "2020-06-01T12:00:00",
+        );
+        for (content, date) in [
+            ("fn a() {}\n", "2020-06-01T12:00:00"),
+            ("fn b() {}\n", "2021-06-01T12:00:00"),
+        ] {
+            write_file(repo.path(), "lib.rs", content);
+            git_at(repo.path(), &["add", "lib.rs"], date);
+            git_at(repo.path(), &["commit", "-qm", "Work"], date);
+        }
+
+        let date_squash = "2022-06-01T12:00:00";
+        git_at(repo.path(), &["checkout", "-q", "-"], date_squash);
+        git_at(
+            repo.path(),
+            &["merge", "-q", "--squash", "feature"],
+            date_squash,
+        );
+        git_at(repo.path(), &["commit", "-qm", "Squashed"], date_squash);
+
+        let dates =
+            get_commit_dates_for_file("lib.rs", repo_str(&repo), &GitDirs::default(), &[]).await;
+        assert_eq!(dates.len(), 1);
+        assert_eq!(years_from_commit_dates("lib.rs", &dates), "2022");
+    }
+
+    #[tokio::test]
+    async fn test_batched_commit_dates_equal_per_file() {
+        let repo = create_repo(
+            &[
+                ("main.rs", "fn main() {}\n"),
+                ("a b.rs", "fn a() {}\n"),
+                ("[x].rs", "fn x() {}\n"),
+                ("src/lib.rs", "fn lib() {}\n"),
+            ],
+            "2019-06-01T12:00:00",
+        );
+        let date_feature = "2020-06-01T12:00:00";
+        git_at(
+            repo.path(),
+            &["checkout", "-q", "-b", "feature"],
+            date_feature,
+        );
+        write_file(repo.path(), "main.rs", "fn main() {\n}\n");
+        write_file(repo.path(), "a b.rs", "fn a() {\n}\n");
+        write_file(repo.path(), "src/lib.rs", "fn lib() {\n}\n");
+        git_at(repo.path(), &["commit", "-qam", "Change"], date_feature);
+
+        let date_merge = "2022-06-01T12:00:00";
+        git_at(repo.path(), &["checkout", "-q", "-"], date_merge);
+        git_at(
+            repo.path(),
+            &["merge", "-q", "--no-ff", "--no-commit", "feature"],
+            date_merge,
+        );
+        write_file(repo.path(), "[x].rs", "fn x() {\n}\n");
+        write_file(repo.path(), "new.rs", "fn new() {}\n");
+        git_at(repo.path(), &["add", "."], date_merge);
+        git_at(repo.path(), &["commit", "-qm", "Merge"], date_merge);
+        let merge_sha = head_sha(repo.path());
+
+        let nested = repo.path().join("src");
+        let runs: [(&str, &[&str]); 2] = [
+            (
+                repo_str(&repo),
+                &[
+                    "main.rs",
+                    "a b.rs",
+                    "[x].rs",
+                    "new.rs",
+                    "src/lib.rs",
+                    "untracked.rs",
+                ],
+            ),
+            // Paths are relative to a directory below the repository root
+            (nested.to_str().unwrap(), &["lib.rs", "untracked.rs"]),
+        ];
+        let ignore_revs = [merge_sha[..10].to_owned()];
+        for (cwd, files) in runs {
+            for ignore_revs in [&[][..], &ignore_revs[..]] {
+                let batched =
+                    get_commit_dates_for_files(files, cwd, &GitDirs::default(), ignore_revs, 2)
+                        .await
+                        .unwrap();
+                assert_eq!(batched.len(), files.len());
+                for file in files {
+                    let per_file =
+                        get_commit_dates_for_file(file, cwd, &GitDirs::default(), ignore_revs)
+                            .await;
+                    assert!(!per_file.is_empty() || *file == "untracked.rs", "{}", file);
+                    assert_eq!(batched[*file], per_file, "{}", file);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_added_from_add_commit() {
+        let repo = create_repo(&[("main.rs", "fn main() {}\n")], "2021-06-01T12:00:00");
+        // A commit with a skewed date before the one adding the file
+        write_file(repo.path(), "main.rs", "fn main() {\n}\n");
+        git_at(
+            repo.path(),
+            &["commit", "-qam", "Skewed"],
+            "2018-06-01T12:00:00",
+        );
+
+        let dates =
+            get_commit_dates_for_file("main.rs", repo_str(&repo), &GitDirs::default(), &[]).await;
+        assert_eq!(years_from_commit_dates("main.rs", &dates), "2018-2021");
+
+        let added = get_add_date_for_file("main.rs", repo_str(&repo), &GitDirs::default())
+            .await
+            .unwrap();
+        let dates = with_added_date(dates, added);
+        assert_eq!(years_from_commit_dates("main.rs", &dates), "2021");
+
+        assert!(
+            get_add_date_for_file("missing.rs", repo_str(&repo), &GitDirs::default())
+                .await
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_years_from_unordered_commit_dates() {
+        let dates: Vec<_> = [
+            "2020-03-01 10:00:00 +0100",
+            "2022-03-01 10:00:00 +0100",
+            "2019-05-01 10:00:00 +0200",
+        ]
+        .iter()
+        .map(|d| DateTime::parse_from_str(d, "%Y-%m-%d %H:%M:%S %z").unwrap())
+        .collect();
+        assert_eq!(years_from_commit_dates("file.rs", &dates), "2019-2022");
+    }
+
+    #[test]
+    fn test_years_from_commit_dates_in_one_year() {
+        let dates: Vec<_> = [
+            "2021-09-01 10:00:00 +0100",
+            "2021-03-01 10:00:00 +0100",
+            "2021-09-01 10:00:00 +0100",
+        ]
+        .iter()
+        .map(|d| DateTime::parse_from_str(d, "%Y-%m-%d %H:%M:%S %z").unwrap())
+        .collect();
+        assert_eq!(years_from_commit_dates("file.rs", &dates), "2021");
+    }
+
+    #[tokio::test]
+    async fn test_years_of_commits_in_one_year_are_stable() {
+        let repo = create_repo(&[("main.rs", "fn main() {}\n")], "2021-03-01T12:00:00");
+        let mut all_years = Vec::new();
+        for (nr, date) in ["", "2021-06-01T12:00:00", "2021-12-01T12:00:00"]
+            .iter()
+            .enumerate()
+        {
+            if !date.is_empty() {
+                write_file(repo.path(), "main.rs", &format!("fn main() {{ {} }}\n", nr));
+                git_at(repo.path(), &["commit", "-q", "-am", "Change"], date);
+            }
+            let dates =
+                get_commit_dates_for_file("main.rs", repo_str(&repo), &GitDirs::default(), &[])
+                    .await;
+            assert_eq!(dates.len(), nr + 1);
+            all_years.push(years_from_commit_dates("main.rs", &dates));
+        }
+        assert_eq!(all_years, vec!["2021", "2021", "2021"]);
+    }
+
+    #[test]
+    fn test_read_ignore_revs_file() {
+        let dir = tempfile::tempdir().unwrap();
+        write_file(
+            dir.path(),
+            ".git-blame-ignore-revs",
+            "# Format with rustfmt\nabc123\n\n  def456 # trailing comment\n",
+        );
+        assert_eq!(
+            read_ignore_revs_file(&dir.path().join(".git-blame-ignore-revs")).unwrap(),
+            vec!["abc123", "def456"]
+        );
+    }
+}
@@ -0,0 +1,71 @@
+//! Confirm changes of single files in interactive mode.
+
+use crate::CError;
+use std::io::{BufRead, Write};
+
+/// Answer to the question whether to apply a change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Answer {
+    /// Apply this change
+    Yes,
+    /// Leave this file as it is
+    No,
+    /// Apply this and all following changes without asking
+    All,
+    /// Leave this and all following files as they are
+    Quit,
+}
+
+/// Describe a change on `output` and ask whether to apply it until a valid
+/// answer is read from `input`. The end of `input` counts as quit.
+pub fn prompt(
+    message: &str,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> Result<Answer, CError> {
+    writeln!(output, "{}", message)?;
+    loop {
+        write!(output, "Apply? [y]es/[n]o/[a]ll/[q]uit: ")?;
+        output.flush()?;
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 {
+            return Ok(Answer::Quit);
+        }
+        match answer.trim().to_lowercase().as_str() {
+            "y" | "yes" => return Ok(Answer::Yes),
+            "n" | "no" => return Ok(Answer::No),
+            "a" | "all" => return Ok(Answer::All),
+            "q" | "quit" => return Ok(Answer::Quit),
+            _ => writeln!(output, "Please answer y, n, a or q")?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{prompt, Answer};
+
+    #[test]
+    fn test_prompt() {
+        let mut output = Vec::new();
+        let answer = prompt("File a.py has no copyright", &mut &b"y\n"[..], &mut output).unwrap();
+        assert_eq!(answer, Answer::Yes);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "File a.py has no copyright\nApply? [y]es/[n]o/[a]ll/[q]uit: "
+        );
+
+        let mut output = Vec::new();
+        let answer = prompt("", &mut &b"maybe\nALL\n"[..], &mut output).unwrap();
+        assert_eq!(answer, Answer::All);
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("Please answer y, n, a or q"));
+
+        let answer = prompt("", &mut &b"no\n"[..], &mut Vec::new()).unwrap();
+        assert_eq!(answer, Answer::No);
+        let answer = prompt("", &mut &b""[..], &mut Vec::new()).unwrap();
+        assert_eq!(answer, Answer::Quit);
+    }
+}
@@ -1,84 +1,1205 @@
 //! Extract added/last modified times from git history and add/update copyright note.
 
 pub mod config;
+pub mod editorconfig;
 pub mod error;
 pub mod file_ops;
+pub mod fs_ops;
 pub mod git_ops;
+pub mod interactive;
+pub mod patch;
 pub mod regex_ops;
+pub mod state;
+pub mod summary;
+pub mod template;
+#[cfg(test)]
+pub(crate) mod test_utils;
+pub mod timings;
 
+use chrono::{DateTime, FixedOffset};
+use config::file_type;
 pub use config::Config;
+use editorconfig::end_of_line;
 pub use error::CError;
-use file_ops::read_write_copyright;
-use futures::future::join_all;
-use futures::FutureExt;
+use file_ops::{diff_copyright, read_write_copyright, Changes, CopyrightRegexes, FileStatus};
+use fs_ops::{get_files_in_dir, get_modification_dates_for_file, get_sibling_files};
+use futures::future::ready;
+use futures::{FutureExt, StreamExt};
 use git_ops::check_for_changes;
-use git_ops::get_added_mod_times_for_file;
-use git_ops::get_files_on_ref;
-use regex_ops::CopyrightCache;
-use regex_ops::{generate_base_regex, generate_copyright_line};
-use serde::Deserialize;
+use git_ops::get_commit_dates_for_files;
+use git_ops::get_git_dir;
+use git_ops::read_ignore_revs_file;
+use git_ops::{are_plausible_years, get_commit_dates_for_file, is_younger_than};
+use git_ops::{get_add_date_for_file, with_added_date};
+use git_ops::{get_blob_sizes, get_files_changed_since, get_files_on_ref, get_submodules};
+use git_ops::{year_list_from_commit_dates, years_from_commit_dates};
+use git_ops::{GitDirs, YearBounds, YearFormat};
+use interactive::{prompt, Answer};
+use patch::Patch;
+use regex_ops::generate_copyright_line;
+use regex_ops::{regexes_for_file_type, CopyrightCache, Leniency};
+use serde::{Deserialize, Serialize};
+use state::{check_template_state, template_hash, write_template_state};
 use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
 use std::hash::Hasher;
-use std::path::Path;
+use std::io::{BufRead, Write};
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+use summary::{estimate_changes, summarize_by_dir, write_audit_report, write_dir_summary};
+use template::Template;
+use timings::{write_timings, Phase, Timings};
 
-#[derive(Debug, Deserialize, Hash, PartialEq)]
+/// Files processed concurrently per available core by default.
+///
+/// Processing a file mostly waits for `git log`, so more files than cores keep
+/// the machine busy. The bound keeps the number of spawned git processes and
+/// open files in check for large repositories.
+pub const FILES_PER_CORE: usize = 4;
+
+/// Default number of files processed concurrently.
+pub fn default_concurrency() -> usize {
+    std::thread::available_parallelism()
+        .map(|cores| cores.get())
+        .unwrap_or(1)
+        * FILES_PER_CORE
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Hash, PartialEq, Eq)]
 #[serde(untagged)]
 pub enum CommentSign {
+    /// Files are intentionally left without copyright, `~` in the config
+    Skip,
     LeftOnly(String),
     Enclosing(String, String),
 }
 
+/// Source of the years of files, e.g. a changelog, used instead of the git history.
+pub trait YearProvider: std::fmt::Debug + Send + Sync {
+    /// Get the years of a file like `2020` or `2020-2022`, `filepath` is
+    /// relative to the repository.
+    fn years_for(&self, filepath: &Path) -> Result<String, CError>;
+}
+
+/// Event of a file while it is processed, see `EventHandler`.
+#[derive(Debug)]
+pub enum FileEvent<'a> {
+    /// Processing of the file started
+    Started { filepath: &'a str },
+    /// The file was processed, nothing was written in check mode
+    Finished {
+        filepath: &'a str,
+        status: &'a FileStatus,
+    },
+    /// Processing the file failed
+    Failed {
+        filepath: &'a str,
+        error: &'a CError,
+    },
+}
+
+/// Receiver of the events of files as they are processed, e.g. to show live
+/// progress when embedding the crate. Files are processed concurrently, so the
+/// events of different files interleave.
+pub trait EventHandler: std::fmt::Debug + Send + Sync {
+    fn on_event(&self, event: FileEvent<'_>);
+}
+
+/// Whether tracked files are checked for changes, see `check_for_changes`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ChangesCheck {
+    /// Check for changes after checking the copyrights
+    #[default]
+    After,
+    /// Only check the copyrights
+    Skip,
+    /// Only check for changes, e.g. as a separate step of a pipeline
+    Only,
+}
+
+/// Format of the files failing a check.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// Sorted list on `diagnostics`
+    #[default]
+    Text,
+    /// GitHub Actions workflow commands on `report`, see `report_github_annotations`
+    Github,
+}
+
+impl std::str::FromStr for ReportFormat {
+    type Err = CError;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "text" => Ok(ReportFormat::Text),
+            "github" => Ok(ReportFormat::Github),
+            other => Err(CError::ConfigError(format!("Unknown format {}", other))),
+        }
+    }
+}
+
+/// Options for a single run over a repository.
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    /// Name in copyright
+    pub name: String,
+    /// Template used to generate copyright notes, overrides the template of the config
+    pub template: Option<String>,
+    /// Previous template whose notes are rewritten with `template`
+    pub reformat_from: Option<String>,
+    /// Allow templates without `{years}`, such copyrights are added but never updated
+    pub yearless: bool,
+    /// Keep copyrights of other holders and add ours below them
+    pub multi_holder: bool,
+    /// Leave empty files without copyright
+    pub skip_empty: bool,
+    /// Use the most common comment sign of sibling files for files of unknown type
+    pub infer_comment_sign: bool,
+    /// Leave files without copyright which were added less than this many days ago
+    pub min_age_days: Option<u32>,
+    /// Commits which are not considered for the years, e.g. bulk formatting commits
+    pub ignore_revs: Vec<String>,
+    /// Also ignore commits listed in `.git-blame-ignore-revs` of the repository
+    pub blame_ignore_revs: bool,
+    /// Take the year a file was added from the first commit adding it
+    pub added_from_add_commit: bool,
+    /// Years used for all files instead of the git history, e.g. `2020-2022`
+    pub force_years: Option<String>,
+    /// Format of the years written and detected in copyrights
+    pub year_format: YearFormat,
+    /// Write every year with a commit as a sorted list instead of a range, see
+    /// `year_list_from_commit_dates`
+    pub year_list: bool,
+    /// Bounds of the years from the history, not applied to `force_years`
+    pub year_bounds: YearBounds,
+    /// Source of years used instead of the git history, overridden by `force_years`
+    pub year_provider: Option<Arc<dyn YearProvider>>,
+    /// Receiver of the events of each file in addition to the report
+    pub event_handler: Option<Arc<dyn EventHandler>>,
+    /// Process all files of a plain directory with years from their modification times
+    pub no_git: bool,
+    /// Git directory and work tree if git does not find them from the repository path
+    pub git_dirs: GitDirs,
+    /// Also process the files of initialized submodules with years from their own history
+    pub include_submodules: bool,
+    /// Accept copyrights with irregular whitespace and rewrite them canonically
+    pub lenient_whitespace: bool,
+    /// Accept copyrights differing in case or punctuation and rewrite them canonically
+    pub lenient_text: bool,
+    /// Accept copyrights anywhere in a line and update them in place
+    pub unanchored: bool,
+    /// Accept copyrights lacking the right comment sign and add it
+    pub lenient_right_sign: bool,
+    /// Accept indented copyrights and keep their indentation
+    pub lenient_indent: bool,
+    /// Accept copyrights of any holder and only update their years
+    pub keep_holder: bool,
+    /// Only process files changed since the merge base of this ref and `HEAD`
+    pub changed_since: Option<String>,
+    /// Report which pattern of the config ignores a file
+    pub explain_ignores: bool,
+    /// Only list the files which would be processed, sorted, without reading their history
+    pub list_candidates: bool,
+    /// Only check that all files which would be processed have a comment sign
+    pub verify_coverage: bool,
+    /// Only process files with these extensions, all files if empty
+    pub only_ext: Vec<String>,
+    /// Only process the first files, e.g. to try out a configuration
+    pub max_files: Option<usize>,
+    /// Skip files larger than this many bytes, e.g. generated files
+    pub max_file_size: Option<u64>,
+    /// Compare the size of the blobs tracked on `HEAD` with `max_file_size`
+    /// instead of the size of the files, not done without git
+    pub size_from_git: bool,
+    /// Summarize results per top-level directory
+    pub by_dir: bool,
+    /// Report the time spent per phase
+    pub timings: bool,
+    /// Only update existing copyrights, leave files without copyright as they are
+    pub update_only: bool,
+    /// Only add copyrights to files without one, leave existing copyrights as they are
+    pub add_only: bool,
+    /// Leave copyrights whose years only need a later end year, e.g. of archived files
+    pub no_extend_range: bool,
+    /// Remove our copyrights instead of adding or updating them, e.g. for relicensing
+    pub remove: bool,
+    /// Only report files which need a copyright update without writing them
+    pub check: bool,
+    /// Only write a compliance report of all files, never write files or fail
+    pub audit: bool,
+    /// Write all changes as a patch to this file instead of to the files
+    pub patch_out: Option<PathBuf>,
+    /// Command run on each written file, e.g. a formatter, see `run_post_write_cmd`
+    pub post_write_cmd: Option<String>,
+    /// Ask for confirmation before writing each file, not used with `check`
+    pub interactive: bool,
+    /// Rewrite copyrights even if the template changed since the last run
+    pub confirm_reformat: bool,
+    /// Fail if tracked files changed
+    pub fail_on_diff: bool,
+    /// Whether tracked files are checked for changes, not done without git
+    pub changes_check: ChangesCheck,
+    /// Only report files which were changed or need a change
+    pub quiet: bool,
+    /// Format of the files failing a check
+    pub format: ReportFormat,
+    /// Use `end_of_line` of `.editorconfig` files for the lines added to files
+    pub respect_editorconfig: bool,
+    /// End written files with a newline if they lack one
+    pub ensure_trailing_newline: bool,
+    /// Number of files processed concurrently, see `default_concurrency`
+    pub concurrency: Option<usize>,
+    /// Read the history of this many files per `git log` instead of one per file,
+    /// which does not follow renames
+    pub git_log_batch_size: Option<usize>,
+}
+
+impl RunOptions {
+    /// Get the template to use, the one of `config` if none is set.
+    pub fn template<'a>(&'a self, config: &'a Config) -> &'a str {
+        self.template
+            .as_deref()
+            .unwrap_or_else(|| config.get_template())
+    }
+
+    /// Create the cache of the regexes copyrights are detected with, spaced
+    /// after comment signs as in `config`.
+    pub fn regex_cache(&self, config: &Config) -> CopyrightCache {
+        CopyrightCache::new(
+            &self.name,
+            Leniency {
+                whitespace: self.lenient_whitespace,
+                text: self.lenient_text,
+                unanchored: self.unanchored,
+                missing_right_sign: self.lenient_right_sign,
+                indentation: self.lenient_indent,
+            },
+        )
+        .with_year_format(self.year_format)
+        .with_year_list(self.year_list)
+        .with_keep_holder(self.keep_holder)
+        .with_sign_spacing(config.sign_spacing())
+    }
+
+    /// Parse a template, allowing templates without years if `yearless` is set.
+    pub fn parse_template(&self, template: &str) -> Result<Template, CError> {
+        match self.yearless {
+            true => Template::parse_yearless(template),
+            false => Template::parse(template),
+        }
+    }
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        RunOptions {
+            name: String::new(),
+            template: None,
+            reformat_from: None,
+            yearless: false,
+            multi_holder: false,
+            skip_empty: false,
+            infer_comment_sign: false,
+            min_age_days: None,
+            ignore_revs: Vec::new(),
+            blame_ignore_revs: false,
+            added_from_add_commit: false,
+            force_years: None,
+            year_format: YearFormat::default(),
+            year_list: false,
+            year_bounds: YearBounds::default(),
+            year_provider: None,
+            event_handler: None,
+            no_git: false,
+            git_dirs: GitDirs::default(),
+            include_submodules: false,
+            lenient_whitespace: false,
+            lenient_text: false,
+            unanchored: false,
+            lenient_right_sign: false,
+            lenient_indent: false,
+            keep_holder: false,
+            changed_since: None,
+            explain_ignores: false,
+            list_candidates: false,
+            verify_coverage: false,
+            only_ext: Vec::new(),
+            max_files: None,
+            max_file_size: None,
+            size_from_git: false,
+            by_dir: false,
+            timings: false,
+            update_only: false,
+            add_only: false,
+            no_extend_range: false,
+            remove: false,
+            check: false,
+            audit: false,
+            interactive: false,
+            patch_out: None,
+            post_write_cmd: None,
+            confirm_reformat: false,
+            fail_on_diff: true,
+            changes_check: ChangesCheck::default(),
+            quiet: false,
+            format: ReportFormat::default(),
+            respect_editorconfig: false,
+            ensure_trailing_newline: false,
+            concurrency: None,
+            git_log_batch_size: None,
+        }
+    }
+}
+
+/// Check and update copyrights of all files in a repository.
+///
+/// Per-file results and summaries are written to `report`, e.g. stdout.
+/// Diagnostics like errors of single files and the files failing a check are
+/// written to `diagnostics`, e.g. stderr.
 pub async fn check_repo_copyright(
     repo_path_str: &str,
-    name: &str,
-    fail_on_diff: bool,
+    options: &RunOptions,
+    report: &mut dyn Write,
+    diagnostics: &mut dyn Write,
+) -> Result<(), CError> {
+    let mut stdin = std::io::BufReader::new(std::io::stdin());
+    check_repo_copyright_with_input(repo_path_str, options, report, diagnostics, &mut stdin).await
+}
+
+/// Same as `check_repo_copyright` with answers to the prompts of interactive
+/// mode read from `input`.
+///
+/// In interactive mode, all files are checked first. The files needing a
+/// change are then confirmed and written one after the other on
+/// `diagnostics`, so that prompts are not mixed with the report.
+///
+/// With `include_submodules`, each initialized submodule is processed after
+/// the repository like a repository of its own. The first error is returned
+/// after all of them are processed.
+pub async fn check_repo_copyright_with_input(
+    repo_path_str: &str,
+    options: &RunOptions,
+    report: &mut dyn Write,
+    diagnostics: &mut dyn Write,
+    input: &mut dyn BufRead,
+) -> Result<(), CError> {
+    let mut result =
+        check_single_repo_copyright(repo_path_str, options, report, diagnostics, input).await;
+    if !options.include_submodules || options.no_git {
+        return result;
+    }
+
+    // Submodules are found through the repository, their files are then
+    // processed within their own work tree and history
+    let submodule_options = RunOptions {
+        git_dirs: GitDirs::default(),
+        changed_since: None,
+        ..options.clone()
+    };
+    for submodule in get_submodules(repo_path_str, &options.git_dirs).await? {
+        if !options.quiet {
+            writeln!(report, "Checking submodule {}", submodule)?;
+        }
+        let submodule_path = Path::new(repo_path_str).join(&submodule);
+        let submodule_result = check_single_repo_copyright(
+            &submodule_path.to_string_lossy(),
+            &submodule_options,
+            report,
+            diagnostics,
+            input,
+        )
+        .await;
+        if result.is_ok() {
+            result = submodule_result;
+        }
+    }
+    result
+}
+
+async fn check_single_repo_copyright(
+    repo_path_str: &str,
+    options: &RunOptions,
+    report: &mut dyn Write,
+    diagnostics: &mut dyn Write,
+    input: &mut dyn BufRead,
 ) -> Result<(), CError> {
+    if options.changes_check == ChangesCheck::Only {
+        return check_for_changes(
+            repo_path_str,
+            &options.git_dirs,
+            options.fail_on_diff,
+            report,
+        )
+        .await;
+    }
     let config = Config::global();
+    let mut collected = Collected::default();
+    let timings = &collected.timings;
+    let list_start = Instant::now();
+    let options = &with_blame_ignore_revs(repo_path_str, options)?;
+    let template = options.parse_template(options.template(config))?;
     let repo_path = Path::new(repo_path_str);
-    let files_to_check = get_files_on_ref(repo_path_str, "HEAD").await?;
-    let files_to_check: Vec<&String> = config
+    let template_hash = template_hash(options.template(config));
+    let dry_run = options.check
+        || options.audit
+        || options.list_candidates
+        || options.verify_coverage
+        || options.patch_out.is_some();
+    // Directories without git have no place to keep the template state
+    let state_dir = match dry_run || options.remove || options.no_git {
+        true => None,
+        false => Some(get_git_dir(repo_path_str, &options.git_dirs).await?),
+    };
+    if let Some(state_dir) = state_dir.as_deref() {
+        check_template_state(state_dir, &template_hash, options.confirm_reformat)?;
+    }
+    let files_to_check = match options.changed_since.as_deref() {
+        _ if options.no_git => get_files_in_dir(repo_path)?,
+        Some(since_ref) => {
+            get_files_changed_since(repo_path_str, &options.git_dirs, since_ref).await?
+        }
+        None => get_files_on_ref(repo_path_str, &options.git_dirs, "HEAD").await?,
+    };
+    let files_to_check = without_duplicates(files_to_check);
+    if options.explain_ignores {
+        for filepath in files_to_check.iter() {
+            if let Some(pattern) = config.ignoring_pattern(filepath) {
+                writeln!(
+                    report,
+                    "Ignoring {} because of pattern {}",
+                    filepath, pattern
+                )?;
+            }
+        }
+    }
+    let mut files_to_check: Vec<&String> = config
         .filter_files(files_to_check.iter())
         .into_iter()
-        .filter(|f| repo_path.join(Path::new(f)).is_file())
+        .filter(|f| has_extension(f, &options.only_ext))
+        .filter(|f| is_regular_file(repo_path, f))
         .collect();
+    if let Some(max_file_size) = options.max_file_size {
+        let blob_sizes = match options.size_from_git && !options.no_git {
+            true => Some(get_blob_sizes(repo_path_str, &options.git_dirs, "HEAD").await?),
+            false => None,
+        };
+        files_to_check.retain(|f| {
+            let size = match blob_sizes.as_ref() {
+                Some(blob_sizes) => blob_sizes.get(f.as_str()).copied(),
+                None => repo_path.join(f).metadata().ok().map(|m| m.len()),
+            };
+            match size {
+                Some(size) if size > max_file_size => {
+                    log::info!("Skipping {} with {} bytes", f, size);
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
+    if let Some(max_files) = options.max_files {
+        files_to_check.truncate(max_files);
+    }
+    timings.add(Phase::ListFiles, list_start.elapsed());
 
-    println!("Checking {} files", files_to_check.len());
+    if options.list_candidates {
+        files_to_check.sort();
+        for filepath in files_to_check {
+            writeln!(report, "{}", filepath)?;
+        }
+        return Ok(());
+    }
+    if options.verify_coverage {
+        return verify_coverage(config, &files_to_check, report);
+    }
+
+    let uses_history = !options.no_git
+        && !options.remove
+        && options.force_years.is_none()
+        && options.year_provider.is_none();
+    if let (Some(batch_size), true) = (options.git_log_batch_size, uses_history) {
+        let history_start = Instant::now();
+        let filepaths: Vec<&str> = files_to_check.iter().map(|f| f.as_str()).collect();
+        collected.commit_dates = Some(
+            get_commit_dates_for_files(
+                &filepaths,
+                repo_path_str,
+                &options.git_dirs,
+                &options.ignore_revs,
+                batch_size,
+            )
+            .await?,
+        );
+        timings.add(Phase::GitHistory, history_start.elapsed());
+    }
+
+    if !options.quiet {
+        writeln!(report, "Checking {} files", files_to_check.len())?;
+    }
 
-    let base_regex = generate_base_regex(name);
-    let regex_cache = CopyrightCache::new(&base_regex);
+    let regex_cache = options.regex_cache(Config::global());
+    let old_template = match options.reformat_from.as_deref() {
+        Some(old_template) => Some(options.parse_template(old_template)?),
+        None => None,
+    };
 
+    let interactive = options.interactive && !dry_run;
+    let scan_options = RunOptions {
+        check: options.check || options.audit || interactive,
+        ..options.clone()
+    };
     let check_and_fix_futures: Vec<_> = files_to_check
         .iter()
-        .map(|filepath| check_file_copyright(filepath, repo_path_str, name, &regex_cache))
+        .map(|filepath| {
+            with_events(
+                filepath,
+                options.event_handler.as_deref(),
+                catch_panic(
+                    filepath,
+                    check_file_copyright(
+                        filepath,
+                        repo_path_str,
+                        &scan_options,
+                        &template,
+                        old_template.as_ref(),
+                        &regex_cache,
+                        &collected,
+                    ),
+                ),
+            )
+        })
         .collect();
 
-    let results = join_all(check_and_fix_futures).await;
+    let concurrency = options
+        .concurrency
+        .unwrap_or_else(default_concurrency)
+        .max(1);
+    let mut results: Vec<_> = futures::stream::iter(check_and_fix_futures)
+        .buffered(concurrency)
+        .collect()
+        .await;
+    if interactive {
+        let mut answer = None;
+        for (filepath, result) in files_to_check.iter().zip(results.iter_mut()) {
+            let message = match result
+                .as_ref()
+                .ok()
+                .and_then(|status| status.message(&repo_path.join(filepath)))
+            {
+                Some(message) => message,
+                None => continue,
+            };
+            if !matches!(answer, Some(Answer::All | Answer::Quit)) {
+                answer = Some(prompt(&message, input, diagnostics)?);
+            }
+            *result = match answer {
+                Some(Answer::Yes | Answer::All) => {
+                    catch_panic(
+                        filepath,
+                        check_file_copyright(
+                            filepath,
+                            repo_path_str,
+                            options,
+                            &template,
+                            old_template.as_ref(),
+                            &regex_cache,
+                            &collected,
+                        ),
+                    )
+                    .await
+                }
+                _ => Ok(FileStatus::Skipped),
+            };
+        }
+    }
+    // The audit report lists the changes and errors of all files at the end
+    let audit = options.audit;
+    if !audit {
+        for (filepath, status) in files_to_check.iter().zip(results.iter()) {
+            if let Some(message) = status
+                .as_ref()
+                .ok()
+                .and_then(|status| status.message(&repo_path.join(filepath)))
+            {
+                writeln!(report, "{}", message)?;
+            }
+        }
+    }
+
     let failed: Vec<_> = results.iter().filter(|res| res.is_err()).collect();
-    failed.iter().for_each(|res_err| {
-        println!("Error: {}", res_err.as_ref().unwrap_err());
-    });
+    if !audit {
+        for res_err in failed.iter() {
+            writeln!(diagnostics, "Error: {}", res_err.as_ref().unwrap_err())?;
+        }
+    }
+
+    if options.by_dir {
+        let summaries = summarize_by_dir(
+            files_to_check
+                .iter()
+                .map(|filepath| filepath.as_str())
+                .zip(results.iter()),
+        );
+        write_dir_summary(&summaries, report)?;
+    }
+
+    if options.timings {
+        write_timings(timings, report)?;
+    }
+
+    if audit {
+        write_audit_report(
+            files_to_check
+                .iter()
+                .map(|filepath| filepath.as_str())
+                .zip(results.iter()),
+            report,
+        )?;
+        return Ok(());
+    }
 
     if !failed.is_empty() {
         return Err(CError::FixError);
     }
 
-    check_for_changes(repo_path_str, fail_on_diff).await?;
+    if let Some(patch_out) = options.patch_out.as_ref() {
+        let mut patch_file = std::fs::File::create(patch_out)
+            .map_err(|_| CError::WriteError(patch_out.display().to_string()))?;
+        collected.patch.write(&mut patch_file)?;
+        if !options.quiet {
+            writeln!(
+                report,
+                "Wrote changes of {} files to {}",
+                collected.patch.len(),
+                patch_out.display()
+            )?;
+        }
+        if !options.check {
+            return Ok(());
+        }
+    }
+
+    if options.check {
+        if !options.quiet {
+            let estimate = estimate_changes(results.iter());
+            writeln!(
+                report,
+                "Would modify {} files, adding {} lines",
+                estimate.files, estimate.added_lines
+            )?;
+        }
+        let mut outdated: Vec<(&str, &FileStatus)> = files_to_check
+            .iter()
+            .zip(results.iter())
+            .filter_map(|(filepath, res)| match res {
+                Ok(status) if status.needs_change() => Some((filepath.as_str(), status)),
+                _ => None,
+            })
+            .collect();
+        if !outdated.is_empty() {
+            match options.format {
+                ReportFormat::Text => {
+                    let mut files: Vec<&str> = outdated.iter().map(|(f, _)| *f).collect();
+                    report_outdated_files(&mut files, diagnostics)?;
+                }
+                ReportFormat::Github => report_github_annotations(&mut outdated, report)?,
+            }
+            return Err(CError::CopyrightsOutdated(outdated.len()));
+        }
+        return Ok(());
+    }
+
+    if let Some(post_write_cmd) = options.post_write_cmd.as_deref() {
+        let mut failed = false;
+        for (filepath, _) in files_to_check
+            .iter()
+            .zip(results.iter())
+            .filter(|(_, res)| matches!(res, Ok(status) if status.needs_change()))
+        {
+            if let Err(e) = run_post_write_cmd(post_write_cmd, repo_path, filepath).await {
+                writeln!(diagnostics, "Error: {}", e)?;
+                failed = true;
+            }
+        }
+        if failed {
+            return Err(CError::FixError);
+        }
+    }
+
+    if let Some(state_dir) = state_dir.as_deref() {
+        write_template_state(state_dir, &template_hash)?;
+    }
+    if !options.no_git && options.changes_check == ChangesCheck::After {
+        check_for_changes(
+            repo_path_str,
+            &options.git_dirs,
+            options.fail_on_diff,
+            report,
+        )
+        .await?;
+    }
 
     Ok(())
 }
 
+/// Results of a run collected over all files.
+#[derive(Debug, Default)]
+struct Collected {
+    timings: Timings,
+    patch: Patch,
+    /// Commit dates of all files read in batches before processing them
+    commit_dates: Option<HashMap<String, Vec<DateTime<FixedOffset>>>>,
+}
+
 async fn check_file_copyright(
     filepath: &str,
     repo_path: &str,
-    name: &str,
+    options: &RunOptions,
+    template: &Template,
+    old_template: Option<&Template>,
     regex_cache: &CopyrightCache,
+    collected: &Collected,
+) -> Result<FileStatus, CError> {
+    let timings = &collected.timings;
+    let full_path = Path::new(repo_path).join(filepath);
+    let comment_sign = match Config::global().get_comment_sign(filepath) {
+        Err(CError::UnknownCommentSign(_)) if options.infer_comment_sign => {
+            let siblings = get_sibling_files(&full_path)?;
+            let comment_sign = Config::global()
+                .infer_comment_sign(siblings.iter().map(String::as_str))
+                .ok_or_else(|| CError::UnknownCommentSign(filepath.into()))?;
+            log::info!(
+                "Using comment sign {:?} of sibling files for {}",
+                comment_sign,
+                filepath
+            );
+            comment_sign
+        }
+        comment_sign => comment_sign?,
+    };
+    if *comment_sign == CommentSign::Skip {
+        log::debug!(
+            "Skipping {} whose comment sign is configured to skip",
+            filepath
+        );
+        return Ok(FileStatus::Skipped);
+    }
+    let copyright_symbol = Config::global().get_copyright_symbol(filepath);
+    if options.skip_empty && std::fs::metadata(&full_path).is_ok_and(|m| m.len() == 0) {
+        log::debug!("Skipping empty file {}", filepath);
+        return Ok(FileStatus::Skipped);
+    }
+
+    let years = match (options.force_years.as_deref(), &options.year_provider) {
+        // Notes are removed with any years, so the history is not read
+        _ if options.remove => String::new(),
+        (Some(years), _) => years.to_owned(),
+        (None, Some(year_provider)) => year_provider.years_for(Path::new(filepath))?,
+        (None, None) => {
+            let commit_dates = match options.no_git {
+                true => get_modification_dates_for_file(&full_path),
+                false => {
+                    timings
+                        .time(Phase::GitHistory, async {
+                            let commit_dates = match collected.commit_dates.as_ref() {
+                                Some(commit_dates) => {
+                                    commit_dates.get(filepath).cloned().unwrap_or_default()
+                                }
+                                None => {
+                                    get_commit_dates_for_file(
+                                        filepath,
+                                        repo_path,
+                                        &options.git_dirs,
+                                        &options.ignore_revs,
+                                    )
+                                    .await
+                                }
+                            };
+                            match options.added_from_add_commit {
+                                true => match get_add_date_for_file(
+                                    filepath,
+                                    repo_path,
+                                    &options.git_dirs,
+                                )
+                                .await
+                                {
+                                    Some(added) => with_added_date(commit_dates, added),
+                                    None => commit_dates,
+                                },
+                                false => commit_dates,
+                            }
+                        })
+                        .await
+                }
+            };
+            if let Some(min_age_days) = options.min_age_days {
+                if is_younger_than(&commit_dates, min_age_days) {
+                    log::debug!(
+                        "Skipping {} which was added less than {} days ago",
+                        filepath,
+                        min_age_days
+                    );
+                    return Ok(FileStatus::Skipped);
+                }
+            }
+            match options.year_list {
+                true => year_list_from_commit_dates(
+                    filepath,
+                    &commit_dates,
+                    Config::global().collapse_year_runs(),
+                ),
+                false => years_from_commit_dates(filepath, &commit_dates),
+            }
+        }
+    };
+    if options.force_years.is_none() && !options.remove && !are_plausible_years(&years) {
+        log::warn!(
+            "Skipping {} with implausible years {}, check the dates of its history",
+            filepath,
+            years
+        );
+        return Ok(FileStatus::Skipped);
+    }
+    let years = match options.force_years {
+        Some(_) => years,
+        None => options.year_bounds.apply(&years),
+    };
+    let years = options.year_format.format(&years);
+
+    let template = &template.for_file(filepath);
+    let old_template = old_template.map(|old_template| old_template.for_file(filepath));
+    let old_template = old_template.as_deref();
+    let years_fut = ready(years).shared();
+    let copyright_line_fut = generate_copyright_line(
+        template,
+        &options.name,
+        copyright_symbol,
+        comment_sign,
+        regex_cache.space_after_sign(comment_sign),
+        years_fut.clone(),
+    );
+    let regexes = CopyrightRegexes {
+        current: regex_cache.get_regex(template, comment_sign, copyright_symbol)?,
+        previous: match old_template {
+            Some(old_template) => {
+                Some(regex_cache.get_regex(old_template, comment_sign, copyright_symbol)?)
+            }
+            None => None,
+        },
+        any_holder: match options.multi_holder {
+            true => {
+                Some(regex_cache.get_any_holder_regex(template, comment_sign, copyright_symbol)?)
+            }
+            false => None,
+        },
+        equivalent: Config::global()
+            .get_equivalent_comment_signs(filepath)
+            .iter()
+            .map(|equivalent| regex_cache.get_regex(template, equivalent, copyright_symbol))
+            .collect::<Result<_, _>>()?,
+    };
+    let mut placement = Config::global().get_placement(filepath, comment_sign);
+    placement.trailing_newline = options.ensure_trailing_newline;
+    if options.respect_editorconfig {
+        if let Some(line_ending) = end_of_line(Path::new(repo_path), filepath)? {
+            placement.line_ending = Some(line_ending);
+        }
+    }
+    let changes = Changes {
+        check_only: options.check,
+        add: !options.update_only,
+        update: !options.add_only,
+        extend_range: !options.no_extend_range,
+        remove: options.remove,
+    };
+    if options.patch_out.is_some() {
+        let diff = diff_copyright(
+            full_path,
+            filepath,
+            &regexes,
+            years_fut,
+            copyright_line_fut,
+            &placement,
+            changes,
+        );
+        let (status, diff) = timings.time(Phase::Files, diff).await?;
+        if let Some(diff) = diff {
+            collected.patch.add(filepath, diff);
+        }
+        return Ok(status);
+    }
+    let read_write = read_write_copyright(
+        full_path,
+        &regexes,
+        years_fut,
+        copyright_line_fut,
+        &placement,
+        changes,
+    );
+    timings.time(Phase::Files, read_write).await
+}
+
+/// Notify `event_handler` of a file before and after processing it with `fut`.
+async fn with_events(
+    filepath: &str,
+    event_handler: Option<&dyn EventHandler>,
+    fut: impl Future<Output = Result<FileStatus, CError>>,
+) -> Result<FileStatus, CError> {
+    let event_handler = match event_handler {
+        Some(event_handler) => event_handler,
+        None => return fut.await,
+    };
+    event_handler.on_event(FileEvent::Started { filepath });
+    let result = fut.await;
+    event_handler.on_event(match &result {
+        Ok(status) => FileEvent::Finished { filepath, status },
+        Err(error) => FileEvent::Failed { filepath, error },
+    });
+    result
+}
+
+/// Turn a panic while processing a file into an error, so that other files are still processed.
+async fn catch_panic<T>(
+    filepath: &str,
+    fut: impl Future<Output = Result<T, CError>>,
+) -> Result<T, CError> {
+    match AssertUnwindSafe(fut).catch_unwind().await {
+        Ok(res) => res,
+        Err(panic) => {
+            let message = match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+                (Some(message), _) => message.to_string(),
+                (_, Some(message)) => message.clone(),
+                _ => "unknown panic".into(),
+            };
+            Err(CError::Panicked(filepath.into(), message))
+        }
+    }
+}
+
+/// Add commits from `.git-blame-ignore-revs` to the ignored commits if enabled and present.
+fn with_blame_ignore_revs(repo_path: &str, options: &RunOptions) -> Result<RunOptions, CError> {
+    let mut options = options.clone();
+    let ignore_revs_file = Path::new(repo_path).join(".git-blame-ignore-revs");
+    if options.blame_ignore_revs && ignore_revs_file.is_file() {
+        log::info!("Ignoring commits listed in {}", ignore_revs_file.display());
+        options
+            .ignore_revs
+            .extend(read_ignore_revs_file(&ignore_revs_file)?);
+    }
+    Ok(options)
+}
+
+/// Check if a file exists in the repository and is no symlink.
+///
+/// `filepath` is relative to `repo_path`, which need not be the working
+/// directory of the process. git tracks symlinks as links, so the file they point to (possibly outside of
+/// the repository) must not be modified.
+fn is_regular_file(repo_path: &Path, filepath: &str) -> bool {
+    match repo_path.join(filepath).symlink_metadata() {
+        Ok(metadata) if metadata.file_type().is_symlink() => {
+            log::warn!("Skipping symlink {}", filepath);
+            false
+        }
+        Ok(metadata) => metadata.is_file(),
+        Err(_) => false,
+    }
+}
+
+/// Run `cmd_template` on a written file from the repository root.
+///
+/// The template is split at whitespace without a shell, `{file}` in any of
+/// its arguments is replaced by the path of the file relative to the
+/// repository. The path is appended if there is no `{file}`.
+async fn run_post_write_cmd(
+    cmd_template: &str,
+    repo_path: &Path,
+    filepath: &str,
+) -> Result<(), CError> {
+    let error = |reason: String| CError::PostWriteCmdError {
+        file: filepath.to_owned(),
+        reason,
+    };
+    if cmd_template.trim().is_empty() {
+        return Err(error("the command is empty".into()));
+    }
+    let mut args: Vec<String> = cmd_template
+        .split_whitespace()
+        .map(|arg| arg.replace("{file}", filepath))
+        .collect();
+    if !cmd_template.contains("{file}") {
+        args.push(filepath.to_owned());
+    }
+    let status = tokio::process::Command::new(&args[0])
+        .args(&args[1..])
+        .current_dir(repo_path)
+        .status()
+        .await
+        .map_err(|e| error(format!("{} could not be run: {}", args[0], e)))?;
+    match status.code() {
+        Some(0) => Ok(()),
+        Some(code) => Err(error(format!("{} exited {}", args[0], code))),
+        None => Err(error(format!("{} exited from a signal", args[0]))),
+    }
+}
+
+/// Check that all files have a comment sign in `config`, or are configured to
+/// be skipped, and report the types of those without.
+fn verify_coverage(
+    config: &Config,
+    files: &[&String],
+    report: &mut dyn Write,
+) -> Result<(), CError> {
+    let mut uncovered: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for filepath in files {
+        if config.get_comment_sign(filepath).is_err() {
+            let file_type = file_type(filepath).unwrap_or(filepath);
+            uncovered.entry(file_type).or_default().push(filepath);
+        }
+    }
+    if uncovered.is_empty() {
+        writeln!(report, "All {} files have a comment sign", files.len())?;
+        return Ok(());
+    }
+    for (file_type, filepaths) in uncovered.iter() {
+        writeln!(
+            report,
+            "No comment sign for {} in {} files, e.g. {}",
+            file_type,
+            filepaths.len(),
+            filepaths[0]
+        )?;
+    }
+    Err(CError::UncoveredFileTypes(
+        uncovered.into_keys().map(String::from).collect(),
+    ))
+}
+
+/// Remove repeated files keeping the first occurrence, so that no file is
+/// processed twice if it is listed by several sources.
+fn without_duplicates(mut files: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::with_capacity(files.len());
+    files.retain(|file| seen.insert(file.clone()));
+    files
+}
+
+/// Check if a file has one of `extensions`, any file if there are none.
+///
+/// Compound extensions like `tar.gz` are supported, a leading `.` is optional.
+fn has_extension(filepath: &str, extensions: &[String]) -> bool {
+    if extensions.is_empty() {
+        return true;
+    }
+    let filename = filepath.rsplit('/').next().unwrap_or(filepath);
+    extensions.iter().any(|ext| {
+        let ext = ext.trim_start_matches('.');
+        filename.len() > ext.len() + 1
+            && filename.ends_with(ext)
+            && filename[..filename.len() - ext.len()].ends_with('.')
+    })
+}
+
+/// Write the regexes copyrights are detected with as lines `<file type>: <regex>`
+/// for `file_type` or all file types of the config, see `regexes_for_file_type`.
+pub fn write_regexes(
+    options: &RunOptions,
+    file_type: Option<&str>,
+    writer: &mut dyn Write,
+) -> Result<(), CError> {
+    let config = Config::global();
+    let template = options.parse_template(options.template(config))?;
+    let regex_cache = options.regex_cache(config);
+    let file_types = match file_type {
+        Some(file_type) => vec![file_type],
+        None => config.file_types(),
+    };
+    for file_type in file_types {
+        let regexes = regexes_for_file_type(&regex_cache, &template, config, file_type)?;
+        if regexes.is_empty() {
+            writeln!(writer, "{}: skipped", file_type)?;
+        }
+        for regex in regexes {
+            writeln!(writer, "{}: {}", file_type, regex)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write a sorted list of files which need a copyright update, e.g. for pre-commit hooks.
+pub fn report_outdated_files(
+    files: &mut [&str],
+    writer: &mut (impl Write + ?Sized),
+) -> Result<(), CError> {
+    files.sort_unstable();
+    writeln!(writer, "Files needing a copyright update:")?;
+    for filepath in files.iter() {
+        writeln!(writer, "{}", filepath)?;
+    }
+    Ok(())
+}
+
+/// Write a GitHub Actions `error` workflow command per file needing a
+/// copyright update, sorted by path, which shows as annotation in pull requests.
+pub fn report_github_annotations(
+    outdated: &mut [(&str, &FileStatus)],
+    writer: &mut (impl Write + ?Sized),
 ) -> Result<(), CError> {
-    let comment_sign = Config::global().get_comment_sign(filepath)?;
-    let years_fut = get_added_mod_times_for_file(filepath, repo_path).shared();
-    let copyright_line_fut = generate_copyright_line(name, comment_sign, years_fut.clone());
-    let filepath = Path::new(repo_path).join(filepath);
-    let regex = regex_cache.get_regex(comment_sign)?;
-    read_write_copyright(filepath, regex, years_fut, copyright_line_fut).await
+    outdated.sort_unstable_by_key(|(filepath, _)| *filepath);
+    for (filepath, status) in outdated.iter() {
+        let (line_nr, message) = match status {
+            FileStatus::Updated {
+                line_nr,
+                found_years,
+                years,
+            } => (
+                line_nr + 1,
+                format!(
+                    "Copyright has year(s) {} but should have {}",
+                    found_years, years
+                ),
+            ),
+            FileStatus::Reformatted {
+                line_nr,
+                copyright_line,
+            } => (
+                line_nr + 1,
+                format!(
+                    "Copyright is in a different format, reformat it to {}",
+                    copyright_line
+                ),
+            ),
+            FileStatus::Added { years } => {
+                (1, format!("Copyright missing, add one with {}", years))
+            }
+            FileStatus::Removed { line_nr } => (line_nr + 1, "Copyright to remove".into()),
+            FileStatus::Unchanged | FileStatus::Skipped => continue,
+        };
+        writeln!(
+            writer,
+            "::error file={},line={}::{}",
+            escape_workflow_property(filepath),
+            line_nr,
+            escape_workflow_data(&message)
+        )?;
+    }
+    Ok(())
+}
+
+/// Escape the message of a workflow command.
+fn escape_workflow_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escape a property of a workflow command, which also ends at `:` and `,`.
+fn escape_workflow_property(property: &str) -> String {
+    escape_workflow_data(property)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
 }
 
 pub fn get_hash<T: std::hash::Hash>(obj: &T) -> u64 {
@@ -86,3 +1207,2125 @@ pub fn get_hash<T: std::hash::Hash>(obj: &T) -> u64 {
     obj.hash(&mut hasher);
     hasher.finish()
 }
+
+#[cfg(test)]
+mod test {
+
+    use super::{
+        catch_panic, check_repo_copyright, check_repo_copyright_with_input, default_concurrency,
+        has_extension, report_github_annotations, report_outdated_files, without_duplicates,
+        ChangesCheck, EventHandler, FileEvent, FileStatus, ReportFormat, RunOptions, YearProvider,
+        FILES_PER_CORE,
+    };
+    use crate::git_ops::{current_year, YearBounds, YearFormat};
+    use crate::state::STATE_FILE;
+    use crate::template::DEFAULT_TEMPLATE;
+    use crate::test_utils::{
+        create_repo, git, git_at, head_sha, init_default_config, read_file, repo_str, write_file,
+    };
+    use crate::{CError, CommentSign, Config};
+    use chrono::Utc;
+    use std::io::sink;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_template_from_config() {
+        let config = Config::from_str(
+            r#"
+template: "(c) {years} {name}"
+comment_sign_map: {}
+ignore_files: []
+ignore_dirs: []
+"#,
+        )
+        .unwrap();
+
+        let options = RunOptions::default();
+        assert_eq!(options.template(&config), "(c) {years} {name}");
+        let options = RunOptions {
+            template: Some("Copyright {years}".into()),
+            ..Default::default()
+        };
+        assert_eq!(options.template(&config), "Copyright {years}");
+        assert_eq!(
+            RunOptions::default().template(&Config::default()),
+            DEFAULT_TEMPLATE
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reformat_from_previous_template() {
+        init_default_config();
+        let repo = create_repo(
+            &[
+                ("old.rs", "// (c) 2020 DummyCompany\nfn main() {}\n"),
+                ("new.py", "# Copyright (c) DummyCompany 2020\nprint(1)\n"),
+            ],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            reformat_from: Some("(c) {years} {name}".into()),
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            read_file(repo.path(), "old.rs"),
+            "// Copyright (c) DummyCompany 2020\nfn main() {}\n"
+        );
+        assert_eq!(
+            read_file(repo.path(), "new.py"),
+            "# Copyright (c) DummyCompany 2020\nprint(1)\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_multi_holder() {
+        init_default_config();
+        let repo = create_repo(
+            &[(
+                "a.py",
+                "#!/usr/bin/env python\n# Copyright (c) Jane Doe 2019\n# Copyright (c) John Doe 2019\nprint(1)\n",
+            )],
+            "2020-06-01T12:00:00",
+        );
+        let expected = "#!/usr/bin/env python\n# Copyright (c) Jane Doe 2019\n# Copyright (c) John Doe 2019\n# Copyright (c) DummyCompany 2020\nprint(1)\n";
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            multi_holder: true,
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(read_file(repo.path(), "a.py"), expected);
+
+        // Our copyright is found below the other holders beyond the first lines
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(read_file(repo.path(), "a.py"), expected);
+
+        let options = RunOptions {
+            name: "Jane Doe".into(),
+            ..options
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(
+            read_file(repo.path(), "a.py"),
+            expected.replace("Jane Doe 2019", "Jane Doe 2020")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_empty_file() {
+        init_default_config();
+        let mut options = RunOptions {
+            name: "DummyCompany".into(),
+            fail_on_diff: false,
+            ..Default::default()
+        };
+
+        let repo = create_repo(&[("__init__.py", "")], "2020-06-01T12:00:00");
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(
+            read_file(repo.path(), "__init__.py"),
+            "# Copyright (c) DummyCompany 2020\n"
+        );
+
+        let repo = create_repo(&[("__init__.py", "")], "2020-06-01T12:00:00");
+        options.skip_empty = true;
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(read_file(repo.path(), "__init__.py"), "");
+    }
+
+    #[tokio::test]
+    async fn test_min_age_days() {
+        init_default_config();
+        let repo = create_repo(&[("old.py", "print(1)\n")], "2020-06-01T12:00:00");
+        write_file(repo.path(), "recent.py", "print(2)\n");
+        let now = Utc::now().to_rfc3339();
+        git_at(repo.path(), &["add", "-A"], &now);
+        git_at(repo.path(), &["commit", "-q", "-m", "Add recent"], &now);
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            min_age_days: Some(30),
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            read_file(repo.path(), "old.py"),
+            "# Copyright (c) DummyCompany 2020\nprint(1)\n"
+        );
+        assert_eq!(read_file(repo.path(), "recent.py"), "print(2)\n");
+    }
+
+    #[tokio::test]
+    async fn test_update_only() {
+        init_default_config();
+        let repo = create_repo(
+            &[
+                ("new.py", "print(1)\n"),
+                ("old.py", "# Copyright (c) DummyCompany 2019\nprint(1)\n"),
+            ],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            update_only: true,
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(read_file(repo.path(), "new.py"), "print(1)\n");
+        assert_eq!(
+            read_file(repo.path(), "old.py"),
+            "# Copyright (c) DummyCompany 2020\nprint(1)\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_only() {
+        init_default_config();
+        let repo = create_repo(
+            &[
+                ("new.py", "print(1)\n"),
+                ("old.py", "# Copyright (c) DummyCompany 2019\nprint(1)\n"),
+            ],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            add_only: true,
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(
+            read_file(repo.path(), "new.py"),
+            "# Copyright (c) DummyCompany 2020\nprint(1)\n"
+        );
+        assert_eq!(
+            read_file(repo.path(), "old.py"),
+            "# Copyright (c) DummyCompany 2019\nprint(1)\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_only_check_accepts_outdated_copyrights() {
+        init_default_config();
+        let repo = create_repo(
+            &[("old.py", "# Copyright (c) DummyCompany 2019\nprint(1)\n")],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            add_only: true,
+            check: true,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+
+        write_file(repo.path(), "new.py", "print(1)\n");
+        git(repo.path(), &["add", "new.py"]);
+        git(repo.path(), &["commit", "-m", "new"]);
+        let res = check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink()).await;
+        assert!(matches!(res, Err(CError::CopyrightsOutdated(1))));
+    }
+
+    #[tokio::test]
+    async fn test_max_file_size_from_git() {
+        init_default_config();
+        let large = format!("x = '{}'\n", "x".repeat(100));
+        let repo = create_repo(
+            &[("small.py", "print(1)\n"), ("large.py", &large)],
+            "2020-06-01T12:00:00",
+        );
+        // The generated file is small in the work tree but large as tracked
+        write_file(repo.path(), "large.py", "x = ''\n");
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            max_file_size: Some(100),
+            size_from_git: true,
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(
+            read_file(repo.path(), "small.py"),
+            "# Copyright (c) DummyCompany 2020\nprint(1)\n"
+        );
+        assert_eq!(read_file(repo.path(), "large.py"), "x = ''\n");
+    }
+
+    #[tokio::test]
+    async fn test_post_write_cmd() {
+        init_default_config();
+        let repo = create_repo(
+            &[
+                ("new.py", "print(1)\n"),
+                ("ok.py", "# Copyright (c) DummyCompany 2020\n"),
+            ],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            post_write_cmd: Some("touch {file}.formatted".into()),
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert!(repo.path().join("new.py.formatted").exists());
+        assert!(!repo.path().join("ok.py.formatted").exists());
+
+        write_file(repo.path(), "other.py", "print(2)\n");
+        git(repo.path(), &["add", "other.py"]);
+        git(repo.path(), &["commit", "-m", "other"]);
+        let options = RunOptions {
+            post_write_cmd: Some("false".into()),
+            ..options
+        };
+        let mut diagnostics = Vec::new();
+        let res =
+            check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut diagnostics).await;
+        assert!(matches!(res, Err(CError::FixError)));
+        assert_eq!(
+            String::from_utf8(diagnostics).unwrap(),
+            "Error: Post-write command failed for other.py: false exited 1\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_infer_comment_sign() {
+        init_default_config();
+        let repo = create_repo(
+            &[
+                ("tools/a.py", "print(1)\n"),
+                ("tools/b.py", "print(2)\n"),
+                ("tools/c.rs", "fn main() {}\n"),
+                ("tools/run.unknown", "run\n"),
+            ],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            check: true,
+            ..Default::default()
+        };
+        let res = check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink()).await;
+        assert!(matches!(res, Err(CError::FixError)));
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            infer_comment_sign: true,
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(
+            read_file(repo.path(), "tools/run.unknown"),
+            "# Copyright (c) DummyCompany 2020\nrun\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_include_submodules() {
+        init_default_config();
+        let nested = create_repo(&[("nested.py", "print(3)\n")], "2018-06-01T12:00:00");
+        let submodule = create_repo(&[("sub.py", "print(2)\n")], "2019-06-01T12:00:00");
+        git_at(
+            submodule.path(),
+            &[
+                "-c",
+                "protocol.file.allow=always",
+                "submodule",
+                "add",
+                "-q",
+                repo_str(&nested),
+                "nested",
+            ],
+            "2019-06-01T12:00:00",
+        );
+        git_at(
+            submodule.path(),
+            &["commit", "-q", "-m", "Add nested"],
+            "2019-06-01T12:00:00",
+        );
+        let repo = create_repo(&[("main.py", "print(1)\n")], "2020-06-01T12:00:00");
+        git(
+            repo.path(),
+            &[
+                "-c",
+                "protocol.file.allow=always",
+                "submodule",
+                "add",
+                "-q",
+                repo_str(&submodule),
+                "libs/sub",
+            ],
+        );
+        git(
+            repo.path(),
+            &[
+                "-c",
+                "protocol.file.allow=always",
+                "submodule",
+                "update",
+                "-q",
+                "--init",
+                "--recursive",
+            ],
+        );
+        git(repo.path(), &["commit", "-q", "-m", "Add submodule"]);
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(read_file(repo.path(), "libs/sub/sub.py"), "print(2)\n");
+
+        let options = RunOptions {
+            include_submodules: true,
+            ..options
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(
+            read_file(repo.path(), "main.py"),
+            "# Copyright (c) DummyCompany 2020\nprint(1)\n"
+        );
+        assert_eq!(
+            read_file(repo.path(), "libs/sub/sub.py"),
+            "# Copyright (c) DummyCompany 2019\nprint(2)\n"
+        );
+        assert_eq!(
+            read_file(repo.path(), "libs/sub/nested/nested.py"),
+            "# Copyright (c) DummyCompany 2018\nprint(3)\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_current_year_template() {
+        init_default_config();
+        let repo = create_repo(
+            &[
+                ("new.py", "print(1)\n"),
+                ("old.py", "# Copyright 2015-2019 DummyCompany\nprint(1)\n"),
+            ],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            template: Some("Copyright 2015-{current_year} {name}".into()),
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        let expected = format!(
+            "# Copyright 2015-{} DummyCompany\nprint(1)\n",
+            current_year()
+        );
+        assert_eq!(read_file(repo.path(), "new.py"), expected);
+        assert_eq!(read_file(repo.path(), "old.py"), expected);
+    }
+
+    #[tokio::test]
+    async fn test_file_template() {
+        init_default_config();
+        let repo = create_repo(
+            &[
+                ("src/foo.rs", "fn foo() {}\n"),
+                (
+                    "bar.rs",
+                    "// File: src/foo.rs - Copyright DummyCompany 2020\nfn bar() {}\n",
+                ),
+            ],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            template: Some("File: {file} - Copyright {name} {years}".into()),
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(
+            read_file(repo.path(), "src/foo.rs"),
+            "// File: src/foo.rs - Copyright DummyCompany 2020\nfn foo() {}\n"
+        );
+        // The note of another file is no copyright of this one
+        assert_eq!(
+            read_file(repo.path(), "bar.rs"),
+            "// File: bar.rs - Copyright DummyCompany 2020\n\
+             // File: src/foo.rs - Copyright DummyCompany 2020\nfn bar() {}\n"
+        );
+
+        let check = RunOptions {
+            check: true,
+            ..options
+        };
+        check_repo_copyright(repo_str(&repo), &check, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_respect_editorconfig() {
+        init_default_config();
+        let files = [
+            (
+                ".editorconfig",
+                "root = true\n\n[*.py]\nend_of_line = crlf\n",
+            ),
+            ("a.py", "print(1)\r\n"),
+            ("b.sh", "echo hi\n"),
+        ];
+        let repo = create_repo(&files, "2020-06-01T12:00:00");
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            respect_editorconfig: true,
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(
+            read_file(repo.path(), "a.py"),
+            "# Copyright (c) DummyCompany 2020\r\nprint(1)\r\n"
+        );
+        assert_eq!(
+            read_file(repo.path(), "b.sh"),
+            "# Copyright (c) DummyCompany 2020\necho hi\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ensure_trailing_newline() {
+        init_default_config();
+        let files = [("a.py", "print(1)"), ("b.py", "print(2)\n")];
+        let repo = create_repo(&files, "2020-06-01T12:00:00");
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            ensure_trailing_newline: true,
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(
+            read_file(repo.path(), "a.py"),
+            "# Copyright (c) DummyCompany 2020\nprint(1)\n"
+        );
+        assert_eq!(
+            read_file(repo.path(), "b.py"),
+            "# Copyright (c) DummyCompany 2020\nprint(2)\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_remove() {
+        init_default_config();
+        let files = [
+            ("a.py", "# Copyright (c) DummyCompany 2019\n\nprint(1)\n"),
+            (
+                "b.sh",
+                "#!/bin/sh\n# Copyright (c) DummyCompany 2012-2015\necho hi\n",
+            ),
+            ("c.py", "# Copyright (c) Other 2019\nprint(3)\n"),
+            ("d.py", "print(4)\n"),
+        ];
+        let repo = create_repo(&files, "2020-06-01T12:00:00");
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            remove: true,
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(read_file(repo.path(), "a.py"), "print(1)\n");
+        assert_eq!(read_file(repo.path(), "b.sh"), "#!/bin/sh\necho hi\n");
+        assert_eq!(
+            read_file(repo.path(), "c.py"),
+            "# Copyright (c) Other 2019\nprint(3)\n"
+        );
+        assert_eq!(read_file(repo.path(), "d.py"), "print(4)\n");
+        assert!(!repo.path().join(".git").join(STATE_FILE).exists());
+    }
+
+    #[tokio::test]
+    async fn test_year_list() {
+        init_default_config();
+        let repo = create_repo(
+            &[("a.py", "print(1)\n"), ("b.py", "print(2)\n")],
+            "2019-06-01T12:00:00",
+        );
+        for (content, date) in [
+            ("print(3)\n", "2022-06-01T12:00:00"),
+            ("print(4)\n", "2021-06-01T12:00:00"),
+            ("print(5)\n", "2022-09-01T12:00:00"),
+        ] {
+            write_file(repo.path(), "a.py", content);
+            git(repo.path(), &["add", "a.py"]);
+            git_at(repo.path(), &["commit", "-q", "-m", "change"], date);
+        }
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            year_list: true,
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(
+            read_file(repo.path(), "a.py"),
+            "# Copyright (c) DummyCompany 2019, 2021, 2022\nprint(5)\n"
+        );
+        assert_eq!(
+            read_file(repo.path(), "b.py"),
+            "# Copyright (c) DummyCompany 2019\nprint(2)\n"
+        );
+
+        // The lists written are detected as up to date
+        let check = RunOptions {
+            check: true,
+            ..options
+        };
+        check_repo_copyright(repo_str(&repo), &check, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_year_bounds() {
+        init_default_config();
+        let repo = create_repo(&[("old.py", "print(1)\n")], "2015-06-01T12:00:00");
+        write_file(repo.path(), "new.py", "print(2)\n");
+        git(repo.path(), &["add", "new.py"]);
+        git_at(
+            repo.path(),
+            &["commit", "-q", "-m", "new"],
+            "2021-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            year_bounds: YearBounds {
+                start_floor: Some(2018),
+                until_current_year: true,
+            },
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(
+            read_file(repo.path(), "old.py"),
+            format!(
+                "# Copyright (c) DummyCompany 2018-{}\nprint(1)\n",
+                current_year()
+            )
+        );
+        assert_eq!(
+            read_file(repo.path(), "new.py"),
+            format!(
+                "# Copyright (c) DummyCompany 2021-{}\nprint(2)\n",
+                current_year()
+            )
+        );
+
+        // Files without history are new, so they only get the current year
+        let dir = tempfile::tempdir().unwrap();
+        write_file(dir.path(), "new.py", "print(3)\n");
+        let options = RunOptions {
+            no_git: true,
+            ..options
+        };
+        check_repo_copyright(repo_str(&dir), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(
+            read_file(dir.path(), "new.py"),
+            format!(
+                "# Copyright (c) DummyCompany {}\nprint(3)\n",
+                current_year()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_mode() {
+        init_default_config();
+        let repo = create_repo(
+            &[
+                ("src/b.py", "print(1)\n"),
+                ("a.rs", "fn main() {}\n"),
+                ("ok.py", "# Copyright (c) DummyCompany 2020\n"),
+            ],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            check: true,
+            ..Default::default()
+        };
+        let res = check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink()).await;
+        assert!(matches!(res, Err(CError::CopyrightsOutdated(2))));
+        assert_eq!(read_file(repo.path(), "src/b.py"), "print(1)\n");
+        assert_eq!(read_file(repo.path(), "a.rs"), "fn main() {}\n");
+    }
+
+    #[test]
+    fn test_report_github_annotations() {
+        let updated = FileStatus::Updated {
+            line_nr: 1,
+            found_years: "2019".into(),
+            years: "2019-2020".into(),
+        };
+        let added = FileStatus::Added {
+            years: "2020".into(),
+        };
+        let mut outdated = vec![("src/b.py", &updated), ("a,1.rs", &added)];
+        let mut out = Vec::new();
+        report_github_annotations(&mut outdated, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "::error file=a%2C1.rs,line=1::Copyright missing, add one with 2020\n\
+             ::error file=src/b.py,line=2::Copyright has year(s) 2019 but should have 2019-2020\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_mode_github_format() {
+        init_default_config();
+        let repo = create_repo(&[("a.rs", "fn main() {}\n")], "2020-06-01T12:00:00");
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            check: true,
+            quiet: true,
+            format: ReportFormat::Github,
+            ..Default::default()
+        };
+        let mut report = Vec::new();
+        let mut diagnostics = Vec::new();
+        let res =
+            check_repo_copyright(repo_str(&repo), &options, &mut report, &mut diagnostics).await;
+        assert!(matches!(res, Err(CError::CopyrightsOutdated(1))));
+        assert!(String::from_utf8(report)
+            .unwrap()
+            .ends_with("\n::error file=a.rs,line=1::Copyright missing, add one with 2020\n"));
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_report_outdated_files_sorted() {
+        let mut files = vec!["src/b.py", "a.rs", "src/a.py"];
+        let mut stderr = Vec::new();
+        report_outdated_files(&mut files, &mut stderr).unwrap();
+        assert_eq!(
+            String::from_utf8(stderr).unwrap(),
+            "Files needing a copyright update:\na.rs\nsrc/a.py\nsrc/b.py\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_symlinks_are_skipped() {
+        init_default_config();
+        let outside = tempfile::tempdir().unwrap();
+        write_file(outside.path(), "target.py", "print(1)\n");
+
+        let repo = create_repo(&[("main.py", "print(2)\n")], "2020-06-01T12:00:00");
+        std::os::unix::fs::symlink(
+            outside.path().join("target.py"),
+            repo.path().join("link.py"),
+        )
+        .unwrap();
+        git_at(repo.path(), &["add", "-A"], "2020-06-01T12:00:00");
+        git_at(
+            repo.path(),
+            &["commit", "-q", "-m", "Add link"],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+
+        assert_eq!(read_file(outside.path(), "target.py"), "print(1)\n");
+        assert_eq!(
+            read_file(repo.path(), "main.py"),
+            "# Copyright (c) DummyCompany 2020\nprint(2)\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_report_to_file() {
+        init_default_config();
+        let repo = create_repo(
+            &[
+                ("a.py", "print(1)\n"),
+                ("b.py", "# Copyright (c) DummyCompany 2019\n"),
+            ],
+            "2020-06-01T12:00:00",
+        );
+        let report_dir = tempfile::tempdir().unwrap();
+        let report_path = report_dir.path().join("report.txt");
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        let mut report = std::fs::File::create(&report_path).unwrap();
+        check_repo_copyright(repo_str(&repo), &options, &mut report, &mut sink())
+            .await
+            .unwrap();
+
+        let report = std::fs::read_to_string(report_path).unwrap();
+        let repo_path = repo.path().display();
+        assert_eq!(
+            report,
+            format!(
+                "Checking 2 files\n\
+                 File {repo_path}/a.py has no copyright but should have 2020\n\
+                 File {repo_path}/b.py has copyright with year(s) 2019 on line 0 but should have 2020\n\
+                 Files changed:\n\
+                 a.py\n\
+                 b.py\n"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lenient_whitespace_normalizes_notes() {
+        init_default_config();
+        let repo = create_repo(
+            &[
+                (
+                    "a.rs",
+                    "//  Copyright (c)  DummyCompany 2020\nfn main() {}\n",
+                ),
+                ("b.py", "# Copyright (c) DummyCompany 2019 \nprint(1)\n"),
+            ],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            lenient_whitespace: true,
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            read_file(repo.path(), "a.rs"),
+            "// Copyright (c) DummyCompany 2020\nfn main() {}\n"
+        );
+        assert_eq!(
+            read_file(repo.path(), "b.py"),
+            "# Copyright (c) DummyCompany 2020\nprint(1)\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_blame_ignore_revs() {
+        init_default_config();
+        let repo = create_repo(&[("main.py", "print(1)\n")], "2020-06-01T12:00:00");
+        write_file(repo.path(), "main.py", "print( 1 )\n");
+        git_at(
+            repo.path(),
+            &["commit", "-qam", "Reformat"],
+            "2022-06-01T12:00:00",
+        );
+        write_file(
+            repo.path(),
+            ".git-blame-ignore-revs",
+            &format!("# Reformat\n{}\n", head_sha(repo.path())),
+        );
+
+        let mut options = RunOptions {
+            name: "DummyCompany".into(),
+            check: true,
+            ..Default::default()
+        };
+        let mut report = Vec::new();
+        let _ = check_repo_copyright(repo_str(&repo), &options, &mut report, &mut sink()).await;
+        assert!(String::from_utf8(report)
+            .unwrap()
+            .contains("should have 2020-2022"));
+
+        options.blame_ignore_revs = true;
+        let mut report = Vec::new();
+        let _ = check_repo_copyright(repo_str(&repo), &options, &mut report, &mut sink()).await;
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.contains("should have 2020\n"), "{}", report);
+    }
+
+    #[tokio::test]
+    async fn test_git_log_batch_size() {
+        init_default_config();
+        let files = [
+            ("a.py", "print(1)\n"),
+            ("b.py", "print(2)\n"),
+            ("c.py", "print(3)\n"),
+        ];
+        let repo = create_repo(&files, "2020-06-01T12:00:00");
+        write_file(repo.path(), "b.py", "print(4)\n");
+        git_at(
+            repo.path(),
+            &["commit", "-qam", "Change"],
+            "2022-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            git_log_batch_size: Some(2),
+            ..Default::default()
+        };
+        let res = check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink()).await;
+        assert!(matches!(res, Err(CError::FilesChanged)));
+        assert!(read_file(repo.path(), "a.py").starts_with("# Copyright (c) DummyCompany 2020\n"));
+        assert!(
+            read_file(repo.path(), "b.py").starts_with("# Copyright (c) DummyCompany 2020-2022\n")
+        );
+        assert!(read_file(repo.path(), "c.py").starts_with("# Copyright (c) DummyCompany 2020\n"));
+    }
+
+    #[tokio::test]
+    async fn test_audit() {
+        init_default_config();
+        let files = [
+            ("a.py", "# Copyright (c) DummyCompany 2020\n\nprint(1)\n"),
+            ("b.py", "# Copyright (c) DummyCompany 2019\n\nprint(2)\n"),
+            ("c.py", "print(3)\n"),
+            ("d.unknown", "3\n"),
+        ];
+        let repo = create_repo(&files, "2020-06-01T12:00:00");
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            audit: true,
+            ..Default::default()
+        };
+        let mut report = Vec::new();
+        let mut diagnostics = Vec::new();
+        check_repo_copyright(repo_str(&repo), &options, &mut report, &mut diagnostics)
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            "Checking 4 files\n\
+             Audited 4 files: 1 compliant, 1 outdated, 1 missing, 1 errored, 0 skipped\n\
+             outdated: b.py (2019)\n\
+             missing: c.py\n\
+             errored: d.unknown (No comment sign found for file d.unknown)\n"
+        );
+        assert!(diagnostics.is_empty());
+        for (filepath, content) in files {
+            assert_eq!(read_file(repo.path(), filepath), content);
+        }
+        assert!(!repo.path().join(".git").join(STATE_FILE).exists());
+    }
+
+    #[tokio::test]
+    async fn test_interactive() {
+        init_default_config();
+        let files = [
+            ("a.py", "print(1)\n"),
+            ("b.py", "print(2)\n"),
+            ("c.py", "print(3)\n"),
+        ];
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            interactive: true,
+            fail_on_diff: false,
+            ..Default::default()
+        };
+
+        let repo = create_repo(&files, "2020-06-01T12:00:00");
+        let mut prompts = Vec::new();
+        check_repo_copyright_with_input(
+            repo_str(&repo),
+            &options,
+            &mut sink(),
+            &mut prompts,
+            &mut &b"y\nn\nq\n"[..],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            read_file(repo.path(), "a.py"),
+            "# Copyright (c) DummyCompany 2020\nprint(1)\n"
+        );
+        assert_eq!(read_file(repo.path(), "b.py"), "print(2)\n");
+        assert_eq!(read_file(repo.path(), "c.py"), "print(3)\n");
+        let prompts = String::from_utf8(prompts).unwrap();
+        assert!(prompts.contains("b.py has no copyright but should have 2020"));
+        assert_eq!(prompts.matches("Apply?").count(), 3);
+
+        let repo = create_repo(&files, "2020-06-01T12:00:00");
+        let mut prompts = Vec::new();
+        check_repo_copyright_with_input(
+            repo_str(&repo),
+            &options,
+            &mut sink(),
+            &mut prompts,
+            &mut &b"n\na\n"[..],
+        )
+        .await
+        .unwrap();
+        assert_eq!(read_file(repo.path(), "a.py"), "print(1)\n");
+        assert_eq!(
+            read_file(repo.path(), "b.py"),
+            "# Copyright (c) DummyCompany 2020\nprint(2)\n"
+        );
+        assert_eq!(
+            read_file(repo.path(), "c.py"),
+            "# Copyright (c) DummyCompany 2020\nprint(3)\n"
+        );
+        assert_eq!(
+            String::from_utf8(prompts)
+                .unwrap()
+                .matches("Apply?")
+                .count(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_yearless_template() {
+        init_default_config();
+        let notice = "# Copyright DummyCompany. All rights reserved.\n";
+        let repo = create_repo(
+            &[
+                ("new.py", "print(1)\n"),
+                ("old.py", &format!("{}print(1)\n", notice)),
+            ],
+            "2020-06-01T12:00:00",
+        );
+
+        let mut options = RunOptions {
+            name: "DummyCompany".into(),
+            template: Some("Copyright {name}. All rights reserved.".into()),
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        let res = check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink()).await;
+        assert!(matches!(res, Err(CError::TemplateError(_))));
+
+        options.yearless = true;
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(
+            read_file(repo.path(), "new.py"),
+            format!("{}print(1)\n", notice)
+        );
+        assert_eq!(
+            read_file(repo.path(), "old.py"),
+            format!("{}print(1)\n", notice)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_force_years() {
+        init_default_config();
+        let repo = create_repo(
+            &[
+                ("new.py", "print(1)\n"),
+                ("old.py", "# Copyright (c) DummyCompany 2019\nprint(1)\n"),
+                ("src/lib.rs", "fn main() {}\n"),
+            ],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            force_years: Some("2015-2016".into()),
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(
+            read_file(repo.path(), "new.py"),
+            "# Copyright (c) DummyCompany 2015-2016\nprint(1)\n"
+        );
+        assert_eq!(
+            read_file(repo.path(), "old.py"),
+            "# Copyright (c) DummyCompany 2015-2016\nprint(1)\n"
+        );
+        assert_eq!(
+            read_file(repo.path(), "src/lib.rs"),
+            "// Copyright (c) DummyCompany 2015-2016\nfn main() {}\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_changed_template_needs_confirmation() {
+        init_default_config();
+        let repo = create_repo(&[("a.py", "print(1)\n")], "2020-06-01T12:00:00");
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert!(repo.path().join(".git").join(STATE_FILE).is_file());
+        assert!(!repo.path().join(STATE_FILE).exists());
+
+        let changed = RunOptions {
+            template: Some("Copyright {name} {years}".into()),
+            reformat_from: Some(DEFAULT_TEMPLATE.into()),
+            ..options.clone()
+        };
+        let res = check_repo_copyright(repo_str(&repo), &changed, &mut sink(), &mut sink()).await;
+        assert!(matches!(res, Err(CError::TemplateChanged)));
+        assert_eq!(
+            read_file(repo.path(), "a.py"),
+            "# Copyright (c) DummyCompany 2020\nprint(1)\n"
+        );
+
+        let confirmed = RunOptions {
+            confirm_reformat: true,
+            ..changed.clone()
+        };
+        check_repo_copyright(repo_str(&repo), &confirmed, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(
+            read_file(repo.path(), "a.py"),
+            "# Copyright DummyCompany 2020\nprint(1)\n"
+        );
+        // The new template is remembered
+        check_repo_copyright(repo_str(&repo), &changed, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+
+        // The state is kept in the git directory and without git not at all
+        let status = std::process::Command::new("git")
+            .args(["status", "--porcelain", "--untracked-files=all"])
+            .current_dir(repo.path())
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8(status.stdout).unwrap(), " M a.py\n");
+        let dir = tempfile::tempdir().unwrap();
+        write_file(dir.path(), "a.py", "print(1)\n");
+        let no_git = RunOptions {
+            no_git: true,
+            ..options
+        };
+        check_repo_copyright(repo_str(&dir), &no_git, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_keep_holder() {
+        init_default_config();
+        let repo = create_repo(
+            &[
+                ("a.py", "# Copyright (c) Jane Doe 2019\nprint(1)\n"),
+                ("b.py", "# Copyright (c) Other Corp. 2019\nprint(1)\n"),
+                ("c.py", "print(1)\n"),
+            ],
+            "2021-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            keep_holder: true,
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(
+            read_file(repo.path(), "a.py"),
+            "# Copyright (c) Jane Doe 2021\nprint(1)\n"
+        );
+        assert_eq!(
+            read_file(repo.path(), "b.py"),
+            "# Copyright (c) Other Corp. 2021\nprint(1)\n"
+        );
+        assert_eq!(
+            read_file(repo.path(), "c.py"),
+            "# Copyright (c) DummyCompany 2021\nprint(1)\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_implausible_years_are_not_written() {
+        init_default_config();
+        let repo = create_repo(&[("a.py", "print(1)\n")], "2020-06-01T12:00:00");
+        write_file(repo.path(), "a.py", "print(2)\n");
+        // A commit made with a broken clock
+        git_at(
+            repo.path(),
+            &["commit", "-qam", "Change"],
+            "2099-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(read_file(repo.path(), "a.py"), "print(2)\n");
+    }
+
+    #[tokio::test]
+    async fn test_changes_check() {
+        init_default_config();
+        let repo = create_repo(&[("a.py", "print(1)\n")], "2020-06-01T12:00:00");
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            ..Default::default()
+        };
+
+        let only = RunOptions {
+            changes_check: ChangesCheck::Only,
+            ..options.clone()
+        };
+        check_repo_copyright(repo_str(&repo), &only, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(read_file(repo.path(), "a.py"), "print(1)\n");
+
+        let skip = RunOptions {
+            changes_check: ChangesCheck::Skip,
+            ..options.clone()
+        };
+        let mut report = Vec::new();
+        check_repo_copyright(repo_str(&repo), &skip, &mut report, &mut sink())
+            .await
+            .unwrap();
+        assert!(!String::from_utf8(report)
+            .unwrap()
+            .contains("Files changed:"));
+        assert_eq!(
+            read_file(repo.path(), "a.py"),
+            "# Copyright (c) DummyCompany 2020\nprint(1)\n"
+        );
+
+        let res = check_repo_copyright(repo_str(&repo), &only, &mut sink(), &mut sink()).await;
+        assert!(matches!(res, Err(CError::FilesChanged)));
+    }
+
+    #[tokio::test]
+    async fn test_lenient_indent() {
+        init_default_config();
+        let content = "mod a {\n    // Copyright (c) DummyCompany 2019\n}\n";
+        for (lenient_indent, expected) in [
+            (
+                false,
+                "// Copyright (c) DummyCompany 2020\nmod a {\n    // Copyright (c) DummyCompany 2019\n}\n",
+            ),
+            (true, "mod a {\n    // Copyright (c) DummyCompany 2020\n}\n"),
+        ] {
+            let repo = create_repo(&[("lib.rs", content)], "2020-06-01T12:00:00");
+            let options = RunOptions {
+                name: "DummyCompany".into(),
+                lenient_indent,
+                fail_on_diff: false,
+                ..Default::default()
+            };
+            check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+                .await
+                .unwrap();
+            assert_eq!(read_file(repo.path(), "lib.rs"), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lenient_right_sign() {
+        init_default_config();
+        let repo = create_repo(
+            &[("a.xml", "<!-- Copyright (c) DummyCompany 2019\n<a/>\n")],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            lenient_right_sign: true,
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(
+            read_file(repo.path(), "a.xml"),
+            "<!-- Copyright (c) DummyCompany 2020 -->\n<a/>\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_patch_out() {
+        init_default_config();
+        let repo = create_repo(
+            &[
+                ("new.py", "print(1)\n"),
+                ("old.py", "# Copyright (c) DummyCompany 2019\nprint(1)\n"),
+                (
+                    "src/lib.rs",
+                    "// Copyright (c) DummyCompany 2020\nfn main() {}\n",
+                ),
+                ("run.sh", "#!/bin/sh\necho hi"),
+            ],
+            "2020-06-01T12:00:00",
+        );
+        let patch_dir = tempfile::tempdir().unwrap();
+        let patch_out = patch_dir.path().join("copyright.patch");
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            patch_out: Some(patch_out.clone()),
+            ..Default::default()
+        };
+        let mut report = Vec::new();
+        check_repo_copyright(repo_str(&repo), &options, &mut report, &mut sink())
+            .await
+            .unwrap();
+        assert!(String::from_utf8(report)
+            .unwrap()
+            .contains("Wrote changes of 3 files to "));
+        assert_eq!(read_file(repo.path(), "new.py"), "print(1)\n");
+        assert!(!repo.path().join(".git").join(STATE_FILE).exists());
+
+        git(repo.path(), &["apply", patch_out.to_str().unwrap()]);
+        assert_eq!(
+            read_file(repo.path(), "new.py"),
+            "# Copyright (c) DummyCompany 2020\nprint(1)\n"
+        );
+        assert_eq!(
+            read_file(repo.path(), "old.py"),
+            "# Copyright (c) DummyCompany 2020\nprint(1)\n"
+        );
+        assert_eq!(
+            read_file(repo.path(), "run.sh"),
+            "#!/bin/sh\n# Copyright (c) DummyCompany 2020\necho hi"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shebang_add_then_update() {
+        init_default_config();
+        let files = [
+            ("run.sh", "#!/bin/sh\necho hi\n"),
+            ("main.py", "#!/usr/bin/env python3\nprint(1)\n"),
+            ("only_shebang.sh", "#!/bin/sh"),
+            ("crlf.sh", "#!/bin/sh\r\necho hi\r\n"),
+            ("plain.sh", "echo hi\n"),
+        ];
+        let repo = create_repo(&files, "2020-06-01T12:00:00");
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        let expected = |years: &str| {
+            let copyright = format!("# Copyright (c) DummyCompany {}", years);
+            [
+                ("run.sh", format!("#!/bin/sh\n{}\necho hi\n", copyright)),
+                (
+                    "main.py",
+                    format!("#!/usr/bin/env python3\n{}\nprint(1)\n", copyright),
+                ),
+                ("only_shebang.sh", format!("#!/bin/sh\n{}", copyright)),
+                (
+                    "crlf.sh",
+                    format!("#!/bin/sh\r\n{}\r\necho hi\r\n", copyright),
+                ),
+                ("plain.sh", format!("{}\necho hi\n", copyright)),
+            ]
+        };
+
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        for (filepath, content) in expected("2020") {
+            assert_eq!(read_file(repo.path(), filepath), content, "{}", filepath);
+        }
+
+        // The copyrights added below the shebangs are updated in place
+        git(repo.path(), &["add", "-A"]);
+        git_at(
+            repo.path(),
+            &["commit", "-q", "-m", "copyright"],
+            "2021-06-01T12:00:00",
+        );
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        for (filepath, content) in expected("2020-2021") {
+            assert_eq!(read_file(repo.path(), filepath), content, "{}", filepath);
+        }
+
+        // Another run finds all copyrights up to date
+        git(repo.path(), &["add", "-A"]);
+        git_at(
+            repo.path(),
+            &["commit", "-q", "-m", "update"],
+            "2021-07-01T12:00:00",
+        );
+        let check = RunOptions {
+            check: true,
+            ..options
+        };
+        check_repo_copyright(repo_str(&repo), &check, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_second_run_changes_nothing() {
+        init_default_config();
+        let files = [
+            ("src/main.rs", "fn main() {}\n"),
+            ("src/crlf.rs", "fn a() {}\r\nfn b() {}\r\n"),
+            (
+                "src/outdated.rs",
+                "// Copyright (c) DummyCompany 2012\nfn main() {}\n",
+            ),
+            ("tool.py", "#!/usr/bin/env python3\nprint(1)\n"),
+            ("crlf.sh", "#!/bin/sh\r\necho hi\r\n"),
+            ("no_newline.py", "print(2)"),
+            ("empty.py", ""),
+            ("page.xml", "<?xml version=\"1.0\"?>\n<root/>\n"),
+            ("style.css", "body {}\n"),
+            ("index.php", "<?php\necho 1;\n"),
+            (
+                "irregular.sh",
+                "#!/bin/sh\n#  Copyright (c)  DummyCompany 2020  \necho hi\n",
+            ),
+            ("other.sh", "# Copyright (c) Other 2019\necho hi\n"),
+            (
+                ".editorconfig",
+                "root = true\n[crlf.rs]\nend_of_line = crlf\n",
+            ),
+        ];
+        let variants = [
+            RunOptions::default(),
+            RunOptions {
+                lenient_whitespace: true,
+                ensure_trailing_newline: true,
+                ..Default::default()
+            },
+            RunOptions {
+                unanchored: true,
+                lenient_right_sign: true,
+                ..Default::default()
+            },
+            RunOptions {
+                multi_holder: true,
+                year_format: YearFormat::TwoDigit,
+                ..Default::default()
+            },
+            RunOptions {
+                keep_holder: true,
+                respect_editorconfig: true,
+                ..Default::default()
+            },
+        ];
+        for variant in variants {
+            let repo = create_repo(&files, "2020-06-01T12:00:00");
+            let options = RunOptions {
+                name: "DummyCompany".into(),
+                fail_on_diff: false,
+                ..variant
+            };
+            let contents = || -> Vec<String> {
+                files
+                    .iter()
+                    .map(|(filepath, _)| read_file(repo.path(), filepath))
+                    .collect()
+            };
+
+            check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+                .await
+                .unwrap();
+            let after_first_run = contents();
+            assert_ne!(after_first_run[0], files[0].1);
+            for (content, (filepath, _)) in after_first_run.iter().zip(files) {
+                if filepath.contains("crlf") {
+                    assert!(
+                        content.split_inclusive('\n').all(|l| l.ends_with("\r\n")),
+                        "{:?}: {:?}",
+                        options,
+                        content
+                    );
+                }
+            }
+
+            let mut report = Vec::new();
+            check_repo_copyright(repo_str(&repo), &options, &mut report, &mut sink())
+                .await
+                .unwrap();
+            let report = String::from_utf8(report).unwrap();
+            assert!(!report.contains("File "), "{:?}: {}", options, report);
+            assert_eq!(contents(), after_first_run, "{:?}", options);
+
+            let check = RunOptions {
+                check: true,
+                ..options
+            };
+            check_repo_copyright(repo_str(&repo), &check, &mut sink(), &mut sink())
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_two_digit_years() {
+        init_default_config();
+        let repo = create_repo(
+            &[
+                ("new.py", "print(1)\n"),
+                ("old.py", "# Copyright (c) DummyCompany '19\nprint(1)\n"),
+            ],
+            "2021-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            year_format: YearFormat::TwoDigit,
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(
+            read_file(repo.path(), "new.py"),
+            "# Copyright (c) DummyCompany '21\nprint(1)\n"
+        );
+        assert_eq!(
+            read_file(repo.path(), "old.py"),
+            "# Copyright (c) DummyCompany '21\nprint(1)\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lenient_text_normalizes_notes() {
+        init_default_config();
+        let repo = create_repo(
+            &[
+                (
+                    "a.py",
+                    "# Copyright DummyCompany 2020. All Rights Reserved\nprint(1)\n",
+                ),
+                (
+                    "b.py",
+                    "# copyright DummyCompany 2020 all rights reserved.\nprint(2)\n",
+                ),
+            ],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            template: Some("Copyright {name} {years}. All rights reserved.".into()),
+            lenient_text: true,
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(
+            read_file(repo.path(), "a.py"),
+            "# Copyright DummyCompany 2020. All rights reserved.\nprint(1)\n"
+        );
+        assert_eq!(
+            read_file(repo.path(), "b.py"),
+            "# Copyright DummyCompany 2020. All rights reserved.\nprint(2)\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_no_git() {
+        init_default_config();
+        let dir = tempfile::tempdir().unwrap();
+        write_file(dir.path(), "a.py", "print(1)\n");
+        write_file(dir.path(), "src/b.rs", "fn b() {}\n");
+        write_file(dir.path(), "notes.txt", "notes\n");
+        let modified: std::time::SystemTime =
+            chrono::DateTime::parse_from_rfc3339("2019-05-01T10:00:00+00:00")
+                .unwrap()
+                .into();
+        std::fs::File::options()
+            .write(true)
+            .open(dir.path().join("a.py"))
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            no_git: true,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&dir), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(
+            read_file(dir.path(), "a.py"),
+            "# Copyright (c) DummyCompany 2019\nprint(1)\n"
+        );
+        assert!(read_file(dir.path(), "src/b.rs").starts_with("// Copyright (c) DummyCompany "));
+        assert_eq!(read_file(dir.path(), "notes.txt"), "notes\n");
+    }
+
+    #[tokio::test]
+    async fn test_skipped_comment_sign() {
+        init_default_config();
+        // The config of the tests maps the comment sign of markdown files to `~`
+        assert_eq!(
+            Config::global().get_comment_sign("README.md").unwrap(),
+            &CommentSign::Skip
+        );
+        let repo = create_repo(
+            &[("a.py", "print(1)\n"), ("README.md", "# Readme\n")],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            check: true,
+            ..Default::default()
+        };
+        let (mut report, mut diagnostics) = (Vec::new(), Vec::new());
+        let res =
+            check_repo_copyright(repo_str(&repo), &options, &mut report, &mut diagnostics).await;
+        assert!(matches!(res, Err(CError::CopyrightsOutdated(1))));
+        assert!(!String::from_utf8(report).unwrap().contains("README.md"));
+        assert!(!String::from_utf8(diagnostics)
+            .unwrap()
+            .contains("README.md"));
+
+        let options = RunOptions {
+            check: false,
+            fail_on_diff: false,
+            ..options
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(read_file(repo.path(), "README.md"), "# Readme\n");
+        assert!(read_file(repo.path(), "a.py").starts_with("# Copyright"));
+    }
+
+    #[derive(Debug)]
+    struct ChangelogYears;
+
+    impl YearProvider for ChangelogYears {
+        fn years_for(&self, filepath: &Path) -> Result<String, CError> {
+            match filepath.to_str() {
+                Some("a.py") => Ok("1999".into()),
+                Some("src/b.py") => Ok("2000-2001".into()),
+                _ => Err(CError::ReadError(filepath.display().to_string())),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_year_provider() {
+        init_default_config();
+        let repo = create_repo(
+            &[("a.py", "print(1)\n"), ("src/b.py", "print(2)\n")],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            year_provider: Some(Arc::new(ChangelogYears)),
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(
+            read_file(repo.path(), "a.py"),
+            "# Copyright (c) DummyCompany 1999\nprint(1)\n"
+        );
+        assert_eq!(
+            read_file(repo.path(), "src/b.py"),
+            "# Copyright (c) DummyCompany 2000-2001\nprint(2)\n"
+        );
+
+        write_file(repo.path(), "c.py", "print(3)\n");
+        git(repo.path(), &["add", "-A"]);
+        git(repo.path(), &["commit", "-qm", "Add c.py"]);
+        let res = check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink()).await;
+        assert!(matches!(res, Err(CError::FixError)));
+    }
+
+    #[derive(Debug, Default)]
+    struct CollectEvents(Mutex<Vec<String>>);
+
+    impl EventHandler for CollectEvents {
+        fn on_event(&self, event: FileEvent<'_>) {
+            let event = match event {
+                FileEvent::Started { filepath } => format!("started {}", filepath),
+                FileEvent::Finished { filepath, status } => {
+                    format!("finished {} {:?}", filepath, status)
+                }
+                FileEvent::Failed { filepath, error } => format!("failed {}: {}", filepath, error),
+            };
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_event_handler() {
+        init_default_config();
+        let repo = create_repo(
+            &[
+                ("a.py", "# Copyright (c) DummyCompany 2020\nprint(1)\n"),
+                ("b.py", "print(2)\n"),
+                ("c.unknown", "3\n"),
+            ],
+            "2020-06-01T12:00:00",
+        );
+
+        let events = Arc::new(CollectEvents::default());
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            check: true,
+            event_handler: Some(Arc::clone(&events) as Arc<dyn EventHandler>),
+            ..Default::default()
+        };
+        let res = check_repo_copyright(repo_str(&repo), &options, &mut sink(), &mut sink()).await;
+        assert!(matches!(res, Err(CError::FixError)));
+
+        let mut events = events.0.lock().unwrap().clone();
+        events.sort();
+        assert_eq!(
+            events,
+            [
+                "failed c.unknown: No comment sign found for file c.unknown",
+                "finished a.py Unchanged",
+                "finished b.py Added { years: \"2020\" }",
+                "started a.py",
+                "started b.py",
+                "started c.unknown",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_report_and_diagnostics() {
+        init_default_config();
+        let repo = create_repo(
+            &[("a.py", "print(1)\n"), ("b.unknown", "b\n")],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            check: true,
+            ..Default::default()
+        };
+        let (mut report, mut diagnostics) = (Vec::new(), Vec::new());
+        let res =
+            check_repo_copyright(repo_str(&repo), &options, &mut report, &mut diagnostics).await;
+        assert!(matches!(res, Err(CError::FixError)));
+        let (report, diagnostics) = (
+            String::from_utf8(report).unwrap(),
+            String::from_utf8(diagnostics).unwrap(),
+        );
+        assert!(report.contains("a.py has no copyright"));
+        assert!(!report.contains("Error"));
+        assert!(diagnostics.starts_with("Error: "));
+        assert!(diagnostics.contains("b.unknown"));
+        assert!(!diagnostics.contains("a.py"));
+
+        std::fs::remove_file(repo.path().join("b.unknown")).unwrap();
+        git(repo.path(), &["commit", "-qam", "Remove b.unknown"]);
+        let (mut report, mut diagnostics) = (Vec::new(), Vec::new());
+        let res =
+            check_repo_copyright(repo_str(&repo), &options, &mut report, &mut diagnostics).await;
+        assert!(matches!(res, Err(CError::CopyrightsOutdated(1))));
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.contains("a.py has no copyright"));
+        assert!(report.ends_with("Would modify 1 files, adding 1 lines\n"));
+        assert_eq!(
+            String::from_utf8(diagnostics).unwrap(),
+            "Files needing a copyright update:\na.py\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_explain_ignores() {
+        init_default_config();
+        let repo = create_repo(
+            &[
+                ("a.py", "# Copyright (c) DummyCompany 2020\n"),
+                ("docs/notes.txt", "notes\n"),
+            ],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            explain_ignores: true,
+            ..Default::default()
+        };
+        let mut report = Vec::new();
+        check_repo_copyright(repo_str(&repo), &options, &mut report, &mut sink())
+            .await
+            .unwrap();
+
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.contains("Ignoring docs/notes.txt because of pattern *.txt\n"));
+        assert!(!report.contains("Ignoring a.py"));
+    }
+
+    #[tokio::test]
+    async fn test_list_candidates() {
+        init_default_config();
+        let repo = create_repo(
+            &[
+                ("src/b.py", "print(1)\n"),
+                ("a.py", "print(2)\n"),
+                ("docs/notes.txt", "notes\n"),
+            ],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            list_candidates: true,
+            ..Default::default()
+        };
+        let mut report = Vec::new();
+        check_repo_copyright(repo_str(&repo), &options, &mut report, &mut sink())
+            .await
+            .unwrap();
+
+        assert_eq!(String::from_utf8(report).unwrap(), "a.py\nsrc/b.py\n");
+        assert_eq!(read_file(repo.path(), "a.py"), "print(2)\n");
+    }
+
+    #[tokio::test]
+    async fn test_verify_coverage() {
+        init_default_config();
+        let mut files = vec![
+            ("a.py", "print(1)\n"),
+            ("docs/notes.txt", "notes\n"),
+            ("README.md", "# Readme\n"),
+        ];
+        let repo = create_repo(&files, "2020-06-01T12:00:00");
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            verify_coverage: true,
+            ..Default::default()
+        };
+        let mut report = Vec::new();
+        check_repo_copyright(repo_str(&repo), &options, &mut report, &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            "All 2 files have a comment sign\n"
+        );
+
+        files.extend([("x.abc", "1\n"), ("src/y.abc", "2\n"), ("Makefile2", "3\n")]);
+        let repo = create_repo(&files, "2020-06-01T12:00:00");
+        let mut report = Vec::new();
+        let res = check_repo_copyright(repo_str(&repo), &options, &mut report, &mut sink()).await;
+        match res {
+            Err(e @ CError::UncoveredFileTypes(_)) => assert_eq!(
+                e.to_string(),
+                "No comment sign configured for file types Makefile2, abc"
+            ),
+            other => panic!("Unexpected result {:?}", other),
+        }
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            "No comment sign for Makefile2 in 1 files, e.g. Makefile2\n\
+             No comment sign for abc in 2 files, e.g. src/y.abc\n"
+        );
+        assert_eq!(read_file(repo.path(), "x.abc"), "1\n");
+    }
+
+    #[tokio::test]
+    async fn test_files_are_relative_to_repo_not_cwd() {
+        init_default_config();
+        // Tests run in the crate, where src is a directory
+        assert!(Path::new("src").is_dir());
+        let repo = create_repo(
+            &[("src", "#!/bin/sh\n"), ("only_in_repo.py", "print(1)\n")],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            list_candidates: true,
+            ..Default::default()
+        };
+        let mut report = Vec::new();
+        check_repo_copyright(repo_str(&repo), &options, &mut report, &mut sink())
+            .await
+            .unwrap();
+        assert_eq!(String::from_utf8(report).unwrap(), "only_in_repo.py\nsrc\n");
+    }
+
+    #[tokio::test]
+    async fn test_quiet_without_changes() {
+        init_default_config();
+        let repo = create_repo(
+            &[("a.py", "# Copyright (c) DummyCompany 2020\nprint(1)\n")],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            quiet: true,
+            ..Default::default()
+        };
+        let mut report = Vec::new();
+        check_repo_copyright(repo_str(&repo), &options, &mut report, &mut sink())
+            .await
+            .unwrap();
+        assert!(report.is_empty());
+
+        write_file(repo.path(), "b.py", "print(2)\n");
+        git(repo.path(), &["add", "b.py"]);
+        git(repo.path(), &["commit", "-qm", "Add b.py"]);
+        let options = RunOptions {
+            fail_on_diff: false,
+            ..options
+        };
+        let mut report = Vec::new();
+        check_repo_copyright(repo_str(&repo), &options, &mut report, &mut sink())
+            .await
+            .unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.starts_with("File "));
+        assert!(report.contains("b.py has no copyright"));
+    }
+
+    #[tokio::test]
+    async fn test_only_ext() {
+        init_default_config();
+        let repo = create_repo(
+            &[
+                ("a.py", "a = 1\n"),
+                ("b.rs", "fn b() {}\n"),
+                ("c.sh", "echo c\n"),
+                ("src/d.py", "d = 1\n"),
+            ],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            only_ext: vec!["py".into(), ".rs".into()],
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        let mut report = Vec::new();
+        check_repo_copyright(repo_str(&repo), &options, &mut report, &mut sink())
+            .await
+            .unwrap();
+
+        assert!(String::from_utf8(report)
+            .unwrap()
+            .starts_with("Checking 3 files\n"));
+        for f in ["a.py", "b.rs", "src/d.py"] {
+            assert!(read_file(repo.path(), f).contains("Copyright"));
+        }
+        assert_eq!(read_file(repo.path(), "c.sh"), "echo c\n");
+    }
+
+    #[test]
+    fn test_has_extension() {
+        let exts = ["rs".to_owned(), ".tar.gz".to_owned()];
+        assert!(has_extension("src/main.rs", &exts));
+        assert!(has_extension("backup.tar.gz", &exts));
+        assert!(!has_extension("file.gz", &exts));
+        assert!(!has_extension("src/rs", &exts));
+        assert!(!has_extension("src/.rs", &exts));
+        assert!(!has_extension("main.crs", &exts));
+        assert!(has_extension("main.crs", &[]));
+    }
+
+    #[test]
+    fn test_without_duplicates() {
+        let files = ["b.rs", "a.rs", "b.rs", "c/d.rs", "a.rs"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(without_duplicates(files), ["b.rs", "a.rs", "c/d.rs"]);
+        assert!(without_duplicates(Vec::new()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_max_files() {
+        init_default_config();
+        let repo = create_repo(
+            &[
+                ("a.py", "a = 1\n"),
+                ("b.py", "b = 1\n"),
+                ("c.py", "c = 1\n"),
+            ],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            max_files: Some(2),
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        let mut report = Vec::new();
+        check_repo_copyright(repo_str(&repo), &options, &mut report, &mut sink())
+            .await
+            .unwrap();
+
+        assert!(String::from_utf8(report)
+            .unwrap()
+            .starts_with("Checking 2 files\n"));
+        let stamped = ["a.py", "b.py", "c.py"]
+            .iter()
+            .filter(|f| read_file(repo.path(), f).starts_with("# Copyright"))
+            .count();
+        assert_eq!(stamped, 2);
+    }
+
+    #[test]
+    fn test_default_concurrency() {
+        assert!(default_concurrency() >= FILES_PER_CORE);
+        assert_eq!(default_concurrency() % FILES_PER_CORE, 0);
+    }
+
+    #[tokio::test]
+    async fn test_files_processed_one_at_a_time() {
+        init_default_config();
+        let repo = create_repo(
+            &[
+                ("a.py", "a = 1\n"),
+                ("b.py", "b = 1\n"),
+                ("c.py", "c = 1\n"),
+            ],
+            "2020-06-01T12:00:00",
+        );
+
+        let options = RunOptions {
+            name: "DummyCompany".into(),
+            concurrency: Some(1),
+            fail_on_diff: false,
+            ..Default::default()
+        };
+        let mut report = Vec::new();
+        check_repo_copyright(repo_str(&repo), &options, &mut report, &mut sink())
+            .await
+            .unwrap();
+
+        let report = String::from_utf8(report).unwrap();
+        let order: Vec<_> = ["a.py", "b.py", "c.py"]
+            .iter()
+            .map(|f| report.find(&format!("{} has no copyright", f)).unwrap())
+            .collect();
+        assert!(order.windows(2).all(|w| w[0] < w[1]));
+        for f in ["a.py", "b.py", "c.py"] {
+            assert!(read_file(repo.path(), f).starts_with("# Copyright (c) DummyCompany 2020"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_panic_is_reported_as_error() {
+        let res: Result<(), CError> = catch_panic("weird.rs", async {
+            panic!("unexpected input");
+        })
+        .await;
+
+        let err = res.unwrap_err();
+        assert!(matches!(err, CError::Panicked(_, _)));
+        assert_eq!(
+            err.to_string(),
+            "Processing weird.rs panicked: unexpected input"
+        );
+
+        let res = catch_panic("fine.rs", async { Ok(1) }).await;
+        assert_eq!(res.unwrap(), 1);
+    }
+}
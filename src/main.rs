@@ -1,56 +1,749 @@
 //! Add/update copyright notes according to history.
 
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
 use env_logger::TimestampPrecision;
-use git_copyright::{check_repo_copyright, Config};
+use git_copyright::config::DEFAULT_CONFIG;
+use git_copyright::git_ops::{get_toplevel, read_ignore_revs_file, GitDirs};
+use git_copyright::git_ops::{YearBounds, YearFormat};
+use git_copyright::regex_ops::{CopyrightCache, Leniency};
+use git_copyright::template::Template;
+use git_copyright::{check_repo_copyright, CError, ChangesCheck, CommentSign, Config};
+use git_copyright::{write_regexes, ReportFormat, RunOptions};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about)]
-struct Args {
-    /// Path to repository to check
-    #[clap(short, long, default_value = "./")]
-    repo: String,
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
 
-    /// Name in copyright
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Add and update copyrights, used if no subcommand is given
+    Run(RunArgs),
+    /// Do not write files, list files needing a copyright update on stderr and fail
+    Check(CopyrightArgs),
+    /// Write the default configuration to a file as a starting point
+    Init(InitArgs),
+    /// Check that a configuration file and templates are valid
+    Validate(ValidateArgs),
+    /// Check whether a sample line is detected as copyright of a file
+    TestTemplate(TestTemplateArgs),
+    /// Print the configuration in effect with includes resolved
+    PrintConfig(PrintConfigArgs),
+}
+
+#[derive(Args, Debug)]
+struct RunArgs {
+    #[clap(flatten)]
+    copyright: CopyrightArgs,
+
+    /// Do not write files, list files needing a copyright update on stderr and fail
+    #[clap(long)]
+    check: bool,
+
+    /// Show each change and ask whether to apply it before writing the file
+    #[clap(long, conflicts_with = "check")]
+    interactive: bool,
+
+    /// Do not write files and never fail, print a compliance report with counts and the files
+    /// which are not compliant, e.g. for periodic reporting
+    #[clap(
+        long,
+        conflicts_with_all = &["check", "interactive", "only-check-changes", "patch-out", "post-write-cmd", "remove"]
+    )]
+    audit: bool,
+
+    /// Do not fail even if tracked files changed
     #[clap(short, long)]
+    ignore_changes: bool,
+
+    /// Do not check whether tracked files changed
+    #[clap(long, conflicts_with = "ignore-changes")]
+    no_change_check: bool,
+
+    /// Only check whether tracked files changed, e.g. after other steps modifying files
+    #[clap(
+        long,
+        conflicts_with_all = &["check", "interactive", "no-change-check", "no-git"]
+    )]
+    only_check_changes: bool,
+}
+
+#[derive(Args, Debug)]
+struct CopyrightArgs {
+    /// Path to repository to check [default: root of the repository containing ./]
+    #[clap(short, long)]
+    repo: Option<String>,
+
+    /// Use ./ as repository instead of detecting its root if --repo is not given
+    #[clap(long)]
+    no_autodetect: bool,
+
+    /// Git directory passed to all git commands, e.g. a bare repository
+    #[clap(long, conflicts_with = "no-git")]
+    git_dir: Option<String>,
+
+    /// Work tree passed to all git commands, used as repository if --repo is not given
+    #[clap(long, conflicts_with = "no-git")]
+    work_tree: Option<String>,
+
+    /// Also process the files of initialized submodules, with years from their own history
+    #[clap(long, conflicts_with = "no-git")]
+    include_submodules: bool,
+
+    /// Name in copyright
+    #[clap(short, long, env = "GIT_COPYRIGHT_HOLDER")]
     name: String,
 
+    /// Template of the copyright note, `{{` and `}}` produce literal braces
+    /// [default: template of the config or "Copyright (c) {name} {years}"]
+    #[clap(short, long, env = "GIT_COPYRIGHT_TEMPLATE")]
+    template: Option<String>,
+
+    /// Previous template, notes in this format are rewritten with --template
+    #[clap(long)]
+    reformat_from: Option<String>,
+
+    /// Rewrite copyrights even if the template changed since the last run, which is stored in
+    /// git_copyright_state in the git directory
+    #[clap(long)]
+    confirm_reformat: bool,
+
+    /// Allow templates without {years}, such copyrights are added but never updated
+    #[clap(long)]
+    yearless: bool,
+
+    /// Keep copyrights of other holders and add a copyright for --name below them
+    #[clap(long)]
+    multi_holder: bool,
+
     /// YAML file with config to use
     #[clap(short, long, default_value = "")]
     config: String,
 
-    /// Do not fail even if tracked files changed
+    /// Do not add copyrights to empty files
+    #[clap(long)]
+    skip_empty: bool,
+
+    /// End the lines added to files as end_of_line of the applicable .editorconfig files says
+    #[clap(long)]
+    respect_editorconfig: bool,
+
+    /// End the files copyrights are written to with a newline if they lack one
+    #[clap(long)]
+    ensure_trailing_newline: bool,
+
+    /// Use the most common comment sign of the files in the same directory for files of unknown
+    /// type
+    #[clap(long)]
+    infer_comment_sign: bool,
+
+    /// Command to run on each written file, e.g. a formatter. {file} is replaced by the path of
+    /// the file, which is appended if there is no {file}
+    #[clap(long, value_name = "COMMAND", conflicts_with = "patch-out")]
+    post_write_cmd: Option<String>,
+
+    /// Skip files larger than this many bytes, e.g. generated files
+    #[clap(long)]
+    max_file_size: Option<u64>,
+
+    /// Compare the size of the files tracked on HEAD with --max-file-size instead of the size in
+    /// the work tree
+    #[clap(long, requires = "max-file-size", conflicts_with = "no-git")]
+    size_from_git: bool,
+
+    /// Do not add copyrights to files added less than this many days ago
+    #[clap(long)]
+    min_age_days: Option<u32>,
+
+    /// Commit to ignore when computing years, can be given multiple times
+    #[clap(long = "ignore-rev")]
+    ignore_revs: Vec<String>,
+
+    /// File with commits to ignore when computing years, one per line
+    #[clap(long)]
+    ignore_revs_file: Option<String>,
+
+    /// Also ignore commits listed in .git-blame-ignore-revs of the repository
+    #[clap(long)]
+    blame_ignore_revs: bool,
+
+    /// Take the year a file was added from the first commit adding it instead of the
+    /// oldest commit date, e.g. for history with skewed dates
+    #[clap(long)]
+    added_from_add_commit: bool,
+
+    /// Use these years for all files instead of the git history, e.g. 2022 or 2020-2022
+    #[clap(long, value_parser = parse_years)]
+    force_years: Option<String>,
+
+    /// Raise earlier start years from the history to this year, e.g. the founding year of the
+    /// project
+    #[clap(long, value_name = "YEAR", conflicts_with = "force-years")]
+    copyright_start: Option<i32>,
+
+    /// End ranges from the history with the current year instead of the year of the last change
+    #[clap(long, conflicts_with = "force-years")]
+    until_current_year: bool,
+
+    /// Write and detect two-digit years like '19-'21 as in legacy notices
+    #[clap(long)]
+    two_digit_years: bool,
+
+    /// Write every year with a commit as a sorted list like 2019, 2021, 2022 instead of a range,
+    /// see `collapse_year_runs` in the config to write runs of years as ranges
+    #[clap(long, conflicts_with_all = &["copyright-start", "until-current-year"])]
+    year_list: bool,
+
+    /// Process all files of a directory which is no git repository, with years from their
+    /// modification times
+    #[clap(long, conflicts_with = "changed-since")]
+    no_git: bool,
+
+    /// Detect copyrights with irregular whitespace and rewrite them canonically
+    #[clap(long)]
+    lenient_whitespace: bool,
+
+    /// Detect copyrights differing in case or punctuation, e.g. "All Rights Reserved", and
+    /// rewrite them canonically
+    #[clap(long)]
+    lenient_text: bool,
+
+    /// Detect copyrights anywhere in a line, e.g. indented or embedded in markup, and update
+    /// them in place
+    #[clap(long)]
+    unanchored: bool,
+
+    /// Detect copyrights of enclosing comment signs lacking the right sign, e.g. "-->", and add it
+    #[clap(long)]
+    lenient_right_sign: bool,
+
+    /// Detect copyrights indented with whitespace, e.g. within a module block, and keep their
+    /// indentation
+    #[clap(long)]
+    lenient_indent: bool,
+
+    /// Detect copyrights of any holder and only update their years, keeping the holder
+    #[clap(long)]
+    keep_holder: bool,
+
+    /// Only process files changed since the merge base of this ref and HEAD, e.g. main
+    #[clap(long)]
+    changed_since: Option<String>,
+
+    /// Report which pattern of the config ignores a file
+    #[clap(long)]
+    explain_ignores: bool,
+
+    /// Only print the files which would be processed after filtering, sorted, and exit
+    #[clap(long)]
+    list_candidates: bool,
+
+    /// Only check that all files which would be processed have a comment sign in the config and
+    /// fail listing the file types without one
+    #[clap(long, conflicts_with = "list-candidates")]
+    verify_coverage: bool,
+
+    /// Only process files with these extensions, e.g. rs,py
+    #[clap(long, value_delimiter = ',')]
+    only_ext: Vec<String>,
+
+    /// Only process the first N files after filtering, e.g. to try out a config
+    #[clap(long)]
+    max_files: Option<usize>,
+
+    /// Only update existing copyrights, do not add copyrights to files without one
+    #[clap(long)]
+    update_only: bool,
+
+    /// Only add copyrights to files without one, leave existing copyrights as they are. With
+    /// --check, only files without any copyright fail
+    #[clap(
+        long,
+        visible_alias = "require-present",
+        conflicts_with = "update-only"
+    )]
+    add_only: bool,
+
+    /// Leave copyrights as they are if only their end year would advance, e.g. 2019-2021 to
+    /// 2019-2023. Wrong years are still fixed and missing copyrights added
+    #[clap(long, conflicts_with = "add-only")]
+    no_extend_range: bool,
+
+    /// Remove copyrights of ours instead of adding or updating them, e.g. for relicensing. A blank
+    /// line following a removed copyright is removed as well
+    #[clap(
+        long,
+        conflicts_with_all = &["update-only", "add-only", "no-extend-range", "keep-holder", "multi-holder"]
+    )]
+    remove: bool,
+
+    /// Print counts of updated/added/errored files per top-level directory
+    #[clap(long)]
+    by_dir: bool,
+
+    /// Print the time spent listing files, reading git history and reading/writing files
+    #[clap(long)]
+    timings: bool,
+
+    /// Number of files processed concurrently [default: 4 per core]
+    #[clap(long, hide = true)]
+    concurrency: Option<usize>,
+
+    /// Read the history of this many files per git log instead of one per file, which does not
+    /// follow renames. Bounds the output of git log in huge repositories
+    #[clap(long, value_name = "FILES", hide = true, conflicts_with = "no-git")]
+    git_log_batch_size: Option<usize>,
+
+    /// Only report files which were changed or need a change, e.g. in hooks
     #[clap(short, long)]
-    ignore_changes: bool,
+    quiet: bool,
+
+    /// Format of the files failing --check: a list on stderr (text) or GitHub Actions annotations
+    /// on stdout (github)
+    #[clap(long, default_value = "text", possible_values = ["text", "github"])]
+    format: ReportFormat,
+
+    /// Print the regexes copyrights are detected with for this extension or file name, or for all
+    /// of the config without one, and exit
+    #[clap(long, value_name = "FILE_TYPE")]
+    dump_regex: Option<Option<String>>,
+
+    /// Write the report to this file instead of stdout
+    #[clap(short, long)]
+    output: Option<String>,
+
+    /// Do not write files, write all changes as a patch for `git apply` to this file
+    #[clap(long)]
+    patch_out: Option<PathBuf>,
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
+#[derive(Args, Debug)]
+struct InitArgs {
+    /// File to write the configuration to
+    #[clap(default_value = "git_copyright.yml")]
+    path: String,
 
-    env_logger::builder()
-        .format_timestamp(Some(TimestampPrecision::Millis))
-        .init();
+    /// Overwrite the file if it exists
+    #[clap(long)]
+    force: bool,
+}
+
+#[derive(Args, Debug)]
+struct ValidateArgs {
+    /// YAML file with config to validate [default: built-in config]
+    #[clap(short, long, default_value = "")]
+    config: String,
+
+    /// Template of the copyright note to validate [default: template of the config]
+    #[clap(short, long, env = "GIT_COPYRIGHT_TEMPLATE")]
+    template: Option<String>,
+
+    /// Previous template to validate
+    #[clap(long)]
+    reformat_from: Option<String>,
 
-    match args.config.as_str() {
+    /// Allow templates without {years}
+    #[clap(long)]
+    yearless: bool,
+}
+
+#[derive(Args, Debug)]
+struct TestTemplateArgs {
+    /// Line to check, e.g. "// Copyright (c) MyCompany Ltd. 2020"
+    #[clap(short, long)]
+    sample: String,
+
+    /// File whose name selects the comment sign and copyright symbol and which is the path of
+    /// {file}, e.g. main.rs
+    #[clap(short, long)]
+    file: String,
+
+    /// Name in copyright
+    #[clap(short, long, env = "GIT_COPYRIGHT_HOLDER")]
+    name: String,
+
+    /// Template of the copyright note [default: template of the config]
+    #[clap(short, long, env = "GIT_COPYRIGHT_TEMPLATE")]
+    template: Option<String>,
+
+    /// YAML file with config to use [default: built-in config]
+    #[clap(short, long, default_value = "")]
+    config: String,
+
+    /// Allow templates without {years}
+    #[clap(long)]
+    yearless: bool,
+
+    /// Detect copyrights with irregular whitespace
+    #[clap(long)]
+    lenient_whitespace: bool,
+
+    /// Detect copyrights differing in case or punctuation
+    #[clap(long)]
+    lenient_text: bool,
+
+    /// Detect copyrights anywhere in the sample
+    #[clap(long)]
+    unanchored: bool,
+
+    /// Detect copyrights lacking the right comment sign
+    #[clap(long)]
+    lenient_right_sign: bool,
+
+    /// Detect indented copyrights
+    #[clap(long)]
+    lenient_indent: bool,
+
+    /// Print the regex the sample is matched with
+    #[clap(long)]
+    print_regex: bool,
+}
+
+#[derive(Args, Debug)]
+struct PrintConfigArgs {
+    /// YAML file with config to print [default: built-in config]
+    #[clap(short, long, default_value = "")]
+    config: String,
+
+    /// Template of the copyright note [default: template of the config]
+    #[clap(short, long, env = "GIT_COPYRIGHT_TEMPLATE")]
+    template: Option<String>,
+}
+
+/// Subcommands and flags which can be given in front of the `run` flags.
+const COMMANDS_AND_GLOBAL_FLAGS: &[&str] = &[
+    "run",
+    "check",
+    "init",
+    "validate",
+    "test-template",
+    "print-config",
+    "help",
+    "-h",
+    "--help",
+    "-V",
+    "--version",
+];
+
+/// Command line arguments with `run` inserted if no subcommand was given, so
+/// that invocations from before subcommands existed keep working.
+fn args_with_default_command() -> Vec<OsString> {
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    let needs_run = match args.get(1).and_then(|arg| arg.to_str()) {
+        Some(first) => !COMMANDS_AND_GLOBAL_FLAGS.contains(&first),
+        None => false,
+    };
+    if needs_run {
+        args.insert(1, "run".into());
+    }
+    args
+}
+
+/// Check that years are a year or a range of years as in copyrights.
+fn parse_years(years: &str) -> Result<String, String> {
+    let is_year = |year: &str| year.len() == 4 && year.chars().all(|c| c.is_ascii_digit());
+    match years.split_once('-') {
+        Some((first, last)) if is_year(first) && is_year(last) => Ok(years.to_owned()),
+        None if is_year(years) => Ok(years.to_owned()),
+        _ => Err(format!(
+            "{} is no year like 2022 or range like 2020-2022",
+            years
+        )),
+    }
+}
+
+fn load_config(cfg_file: &str) -> Result<Config> {
+    match cfg_file {
         "" => {
             log::info!("Using default configuration");
-            Config::default().assign();
+            Ok(Config::default())
         }
         cfg_file => {
             log::info!("Using config {}", cfg_file);
             Config::from_file(cfg_file)
-                .context(format!("Unable to get config from file {}", cfg_file))?
-                .assign();
+                .context(format!("Unable to get config from file {}", cfg_file))
+        }
+    }
+}
+
+fn parse_template(template: &str, yearless: bool) -> Result<Template, CError> {
+    match yearless {
+        true => Template::parse_yearless(template),
+        false => Template::parse(template),
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse_from(args_with_default_command());
+
+    env_logger::builder()
+        .format_timestamp(Some(TimestampPrecision::Millis))
+        .init();
+
+    match cli.command {
+        Command::Run(args) => {
+            let changes_check = match (args.only_check_changes, args.no_change_check) {
+                (true, _) => ChangesCheck::Only,
+                (false, true) => ChangesCheck::Skip,
+                (false, false) => ChangesCheck::After,
+            };
+            run(
+                args.copyright,
+                args.check,
+                args.interactive,
+                args.audit,
+                !args.ignore_changes,
+                changes_check,
+            )
+            .await
         }
+        Command::Check(args) => run(args, true, false, false, false, ChangesCheck::After).await,
+        Command::Init(args) => init(args),
+        Command::Validate(args) => validate(args),
+        Command::TestTemplate(args) => test_template(args),
+        Command::PrintConfig(args) => print_config(args),
     }
+}
 
+async fn run(
+    args: CopyrightArgs,
+    check: bool,
+    interactive: bool,
+    audit: bool,
+    fail_on_diff: bool,
+    changes_check: ChangesCheck,
+) -> Result<()> {
+    load_config(&args.config)?.assign();
     let start = Instant::now();
-    check_repo_copyright(&args.repo, &args.name, !args.ignore_changes).await?;
-    let duration_s = start.elapsed().as_millis() as f32 / 1000.0;
-    println!("Copyrights checked and updated in {:0.3}s", duration_s);
+    // Git resolves relative paths from the repository, not from ./
+    let git_dirs = GitDirs {
+        git_dir: args
+            .git_dir
+            .map(|git_dir| {
+                std::fs::canonicalize(&git_dir).context(format!("Invalid --git-dir {}", git_dir))
+            })
+            .transpose()?,
+        work_tree: args
+            .work_tree
+            .map(|work_tree| {
+                std::fs::canonicalize(&work_tree)
+                    .context(format!("Invalid --work-tree {}", work_tree))
+            })
+            .transpose()?,
+    };
+    let mut ignore_revs = args.ignore_revs;
+    if let Some(ignore_revs_file) = args.ignore_revs_file.as_deref() {
+        ignore_revs.extend(read_ignore_revs_file(Path::new(ignore_revs_file))?);
+    }
+
+    let options = RunOptions {
+        name: args.name,
+        template: args.template,
+        reformat_from: args.reformat_from,
+        yearless: args.yearless,
+        multi_holder: args.multi_holder,
+        skip_empty: args.skip_empty,
+        respect_editorconfig: args.respect_editorconfig,
+        ensure_trailing_newline: args.ensure_trailing_newline,
+        infer_comment_sign: args.infer_comment_sign,
+        max_file_size: args.max_file_size,
+        post_write_cmd: args.post_write_cmd,
+        size_from_git: args.size_from_git,
+        min_age_days: args.min_age_days,
+        ignore_revs,
+        blame_ignore_revs: args.blame_ignore_revs,
+        added_from_add_commit: args.added_from_add_commit,
+        force_years: args.force_years,
+        year_format: match args.two_digit_years {
+            true => YearFormat::TwoDigit,
+            false => YearFormat::FourDigit,
+        },
+        year_list: args.year_list,
+        year_bounds: YearBounds {
+            start_floor: args.copyright_start,
+            until_current_year: args.until_current_year,
+        },
+        year_provider: None,
+        event_handler: None,
+        no_git: args.no_git,
+        git_dirs,
+        include_submodules: args.include_submodules,
+        lenient_whitespace: args.lenient_whitespace,
+        lenient_text: args.lenient_text,
+        unanchored: args.unanchored,
+        lenient_right_sign: args.lenient_right_sign,
+        lenient_indent: args.lenient_indent,
+        keep_holder: args.keep_holder,
+        changed_since: args.changed_since,
+        explain_ignores: args.explain_ignores,
+        list_candidates: args.list_candidates,
+        verify_coverage: args.verify_coverage,
+        only_ext: args.only_ext,
+        max_files: args.max_files,
+        by_dir: args.by_dir,
+        timings: args.timings,
+        update_only: args.update_only,
+        add_only: args.add_only,
+        no_extend_range: args.no_extend_range,
+        remove: args.remove,
+        check,
+        audit,
+        interactive,
+        patch_out: args.patch_out,
+        confirm_reformat: args.confirm_reformat,
+        fail_on_diff,
+        changes_check,
+        concurrency: args.concurrency,
+        git_log_batch_size: args.git_log_batch_size,
+        quiet: args.quiet,
+        format: args.format,
+    };
+    if let Some(file_type) = args.dump_regex {
+        write_regexes(&options, file_type.as_deref(), &mut std::io::stdout())?;
+        return Ok(());
+    }
+
+    let repo = match (args.repo, options.git_dirs.work_tree.as_ref()) {
+        (Some(repo), _) => repo,
+        (None, Some(work_tree)) => work_tree.display().to_string(),
+        (None, None) if args.no_autodetect || options.no_git => "./".into(),
+        (None, None) => get_toplevel("./", &options.git_dirs)
+            .await
+            .context("Unable to detect repository root, pass --repo")?,
+    };
+    log::info!("Using repository {}", repo);
+    let mut report: Box<dyn Write> = match args.output.as_deref() {
+        Some(output) => Box::new(
+            File::create(output).context(format!("Unable to create output file {}", output))?,
+        ),
+        None => Box::new(std::io::stdout()),
+    };
+    check_repo_copyright(&repo, &options, &mut report, &mut std::io::stderr()).await?;
+    if !options.quiet {
+        let duration_s = start.elapsed().as_millis() as f32 / 1000.0;
+        writeln!(
+            report,
+            "Copyrights checked and updated in {:0.3}s",
+            duration_s
+        )?;
+    }
+
+    Ok(())
+}
+
+fn init(args: InitArgs) -> Result<()> {
+    let path = Path::new(&args.path);
+    if path.exists() && !args.force {
+        anyhow::bail!("{} already exists, pass --force to overwrite it", args.path);
+    }
+    std::fs::write(path, DEFAULT_CONFIG)
+        .context(format!("Unable to write config to {}", args.path))?;
+    println!("Default configuration written to {}", args.path);
 
     Ok(())
 }
+
+fn validate(args: ValidateArgs) -> Result<()> {
+    let config = load_config(&args.config)?;
+    let template = args
+        .template
+        .as_deref()
+        .unwrap_or_else(|| config.get_template());
+    parse_template(template, args.yearless).context(format!("Invalid template {}", template))?;
+    if let Some(reformat_from) = args.reformat_from.as_deref() {
+        parse_template(reformat_from, args.yearless).context("Invalid --reformat-from")?;
+    }
+    println!("Configuration and templates are valid");
+
+    Ok(())
+}
+
+fn test_template(args: TestTemplateArgs) -> Result<()> {
+    let config = load_config(&args.config)?;
+    let template_str = args
+        .template
+        .as_deref()
+        .unwrap_or_else(|| config.get_template());
+    let template = parse_template(template_str, args.yearless)
+        .context(format!("Invalid template {}", template_str))?;
+    let template = template.for_file(&args.file);
+    let comment_sign = config.get_comment_sign(&args.file)?;
+    if *comment_sign == CommentSign::Skip {
+        anyhow::bail!("Files like {} are configured to be skipped", args.file);
+    }
+    let regex = CopyrightCache::new(
+        &args.name,
+        Leniency {
+            whitespace: args.lenient_whitespace,
+            text: args.lenient_text,
+            unanchored: args.unanchored,
+            missing_right_sign: args.lenient_right_sign,
+            indentation: args.lenient_indent,
+        },
+    )
+    .with_sign_spacing(config.sign_spacing())
+    .get_regex(
+        &template,
+        comment_sign,
+        config.get_copyright_symbol(&args.file),
+    )?;
+    if args.print_regex {
+        println!("Regex: {}", regex.as_str());
+    }
+
+    match regex.captures(&args.sample) {
+        Some(cap) => {
+            println!("Sample matches");
+            if let Some(years) = cap.name("years") {
+                println!("Years: {}", years.as_str());
+            }
+            Ok(())
+        }
+        None => anyhow::bail!("Sample does not match the template for {}", args.file),
+    }
+}
+
+fn print_config(args: PrintConfigArgs) -> Result<()> {
+    let config = load_config(&args.config)?;
+    print!("{}", config.to_yaml(args.template.as_deref())?);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{Cli, Command};
+    use clap::Parser;
+
+    #[test]
+    fn test_holder_and_template_from_env() {
+        std::env::set_var("GIT_COPYRIGHT_HOLDER", "EnvCompany");
+        std::env::set_var("GIT_COPYRIGHT_TEMPLATE", "Copyright {name} {years}");
+        let copyright_args = |args: &[&str]| match Cli::parse_from(args).command {
+            Command::Check(args) => args,
+            command => panic!("Unexpected command {:?}", command),
+        };
+
+        let args = copyright_args(&["git_copyright", "check"]);
+        assert_eq!(args.name, "EnvCompany");
+        assert_eq!(args.template.as_deref(), Some("Copyright {name} {years}"));
+
+        let args = copyright_args(&["git_copyright", "check", "-n", "Org", "-t", "{name}"]);
+        assert_eq!(args.name, "Org");
+        assert_eq!(args.template.as_deref(), Some("{name}"));
+
+        std::env::remove_var("GIT_COPYRIGHT_HOLDER");
+        std::env::remove_var("GIT_COPYRIGHT_TEMPLATE");
+        assert!(Cli::try_parse_from(["git_copyright", "check"]).is_err());
+    }
+}
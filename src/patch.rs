@@ -0,0 +1,153 @@
+//! Collect the changes of a run as a patch instead of writing them.
+
+use crate::CError;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Mutex;
+
+/// Number of unchanged lines around a change in a hunk.
+const CONTEXT_LINES: usize = 3;
+
+/// Unified diffs of files by their path, written in the order of the paths.
+#[derive(Debug, Default)]
+pub struct Patch {
+    diffs: Mutex<BTreeMap<String, String>>,
+}
+
+impl Patch {
+    pub fn add(&self, path: &str, diff: String) {
+        self.diffs.lock().unwrap().insert(path.to_owned(), diff);
+    }
+
+    pub fn len(&self) -> usize {
+        self.diffs.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Write all diffs as a single patch which can be applied with `git apply`.
+    pub fn write(&self, out: &mut dyn Write) -> Result<(), CError> {
+        for diff in self.diffs.lock().unwrap().values() {
+            out.write_all(diff.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// Create a unified diff of a file at `path` relative to the repository.
+///
+/// Copyright changes are close to each other at the top of a file, so all
+/// differing lines are put into a single hunk. The diff is empty if the
+/// contents are the same.
+pub fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let prefix = old_lines
+        .iter()
+        .zip(new_lines.iter())
+        .take_while(|(old, new)| old == new)
+        .count();
+    if prefix == old_lines.len() && prefix == new_lines.len() {
+        return String::new();
+    }
+    let suffix = old_lines[prefix..]
+        .iter()
+        .rev()
+        .zip(new_lines[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+
+    let start = prefix.saturating_sub(CONTEXT_LINES);
+    let old_changed_end = old_lines.len() - suffix;
+    let new_changed_end = new_lines.len() - suffix;
+    let old_end = (old_changed_end + CONTEXT_LINES).min(old_lines.len());
+    let new_end = (new_changed_end + CONTEXT_LINES).min(new_lines.len());
+
+    let mut diff = format!(
+        "diff --git a/{path} b/{path}\n--- a/{path}\n+++ b/{path}\n@@ -{} +{} @@\n",
+        hunk_range(start, old_end - start),
+        hunk_range(start, new_end - start),
+        path = path
+    );
+    let hunk_lines = old_lines[start..prefix]
+        .iter()
+        .map(|line| (' ', line))
+        .chain(
+            old_lines[prefix..old_changed_end]
+                .iter()
+                .map(|line| ('-', line)),
+        )
+        .chain(
+            new_lines[prefix..new_changed_end]
+                .iter()
+                .map(|line| ('+', line)),
+        )
+        .chain(
+            old_lines[old_changed_end..old_end]
+                .iter()
+                .map(|line| (' ', line)),
+        );
+    for (sign, line) in hunk_lines {
+        diff.push(sign);
+        diff.push_str(line);
+        if !line.ends_with('\n') {
+            diff.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+    diff
+}
+
+/// Range of a hunk in the unified format, which starts before the first line
+/// if the range is empty.
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", start),
+        count => format!("{},{}", start + 1, count),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{unified_diff, Patch};
+
+    #[test]
+    fn test_unified_diff() {
+        let old = "a\nb\nc\nd\ne\n";
+        let new = "# Copyright 2020\n\na\nb\nc\nd\ne\n";
+        assert_eq!(
+            unified_diff("src/x.py", old, new),
+            "diff --git a/src/x.py b/src/x.py\n--- a/src/x.py\n+++ b/src/x.py\n\
+             @@ -1,3 +1,5 @@\n+# Copyright 2020\n+\n a\n b\n c\n"
+        );
+
+        let old = "#!/bin/sh\n# Copyright 2019\necho hi";
+        let new = "#!/bin/sh\n# Copyright 2019-2020\necho hi";
+        assert_eq!(
+            unified_diff("x.sh", old, new),
+            "diff --git a/x.sh b/x.sh\n--- a/x.sh\n+++ b/x.sh\n@@ -1,3 +1,3 @@\n \
+             #!/bin/sh\n-# Copyright 2019\n+# Copyright 2019-2020\n echo hi\n\
+             \\ No newline at end of file\n"
+        );
+
+        assert_eq!(
+            unified_diff("x.sh", "", "# Copyright 2020\n"),
+            "diff --git a/x.sh b/x.sh\n--- a/x.sh\n+++ b/x.sh\n@@ -0,0 +1,1 @@\n+# Copyright 2020\n"
+        );
+        assert_eq!(unified_diff("x.sh", old, old), "");
+    }
+
+    #[test]
+    fn test_patch_is_sorted_by_path() {
+        let patch = Patch::default();
+        patch.add("b.py", "b\n".into());
+        patch.add("a.py", "a\n".into());
+        assert_eq!(patch.len(), 2);
+
+        let mut out = Vec::new();
+        patch.write(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "a\nb\n");
+    }
+}
@@ -1,115 +1,449 @@
 //! Compile and cache copyright regexes.
 //!
 //! This module contains functions to parse existing copyright notes. Regexes
-//! are compiled once per comment sign and stored in a cache.
+//! are compiled once per template, comment sign and copyright symbol and
+//! stored in a cache.
 
 use crate::get_hash;
+use crate::git_ops::{current_year, YearFormat};
+use crate::template::{Template, TemplatePart};
 use crate::CError;
 use crate::CommentSign;
+use crate::Config;
 use regex::Regex;
 use std::collections::HashMap;
 use std::future::Future;
 use std::sync::Arc;
 use std::sync::RwLock;
 
+/// Template, comment sign and copyright symbol a regex is compiled for.
+type RegexKey = (Template, CommentSign, String);
+
+type RegexMap = RwLock<HashMap<u64, (RegexKey, Arc<Regex>)>>;
+
+/// Differences to the generated copyright line which are still detected as
+/// copyright, such copyrights are rewritten canonically.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Leniency {
+    /// Any non-empty whitespace instead of single spaces
+    pub whitespace: bool,
+    /// Different case and missing punctuation in the text of the template
+    pub text: bool,
+    /// Notes anywhere in a line, e.g. indented or embedded in markup
+    pub unanchored: bool,
+    /// Notes with an enclosing comment sign which lack the right sign
+    pub missing_right_sign: bool,
+    /// Notes indented with whitespace, the indentation is kept on rewrite
+    pub indentation: bool,
+}
+
+/// Number of spaces between comment signs and copyright notes, by default
+/// and per left comment sign.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignSpacing {
+    pub default: usize,
+    pub per_sign: HashMap<String, usize>,
+}
+
+impl Default for SignSpacing {
+    fn default() -> Self {
+        SignSpacing {
+            default: 1,
+            per_sign: HashMap::new(),
+        }
+    }
+}
+
+impl SignSpacing {
+    /// Get the number of spaces after the left sign of `comment_sign`, which
+    /// is also used before its right sign.
+    pub fn for_sign(&self, comment_sign: &CommentSign) -> usize {
+        let left_sign = match comment_sign {
+            CommentSign::Skip => return 0,
+            CommentSign::LeftOnly(left_sign) | CommentSign::Enclosing(left_sign, _) => left_sign,
+        };
+        self.per_sign
+            .get(left_sign)
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
 pub struct CopyrightCache {
-    regexes: RwLock<HashMap<u64, Arc<Regex>>>,
-    base_regex: String,
+    regexes: RegexMap,
+    any_holder_regexes: RegexMap,
+    name: String,
+    leniency: Leniency,
+    year_format: YearFormat,
+    year_list: bool,
+    keep_holder: bool,
+    sign_spacing: SignSpacing,
 }
 
 impl CopyrightCache {
-    pub fn new(base_regex: &str) -> Self {
+    pub fn new(name: &str, leniency: Leniency) -> Self {
         CopyrightCache {
             regexes: RwLock::new(HashMap::new()),
-            base_regex: base_regex.to_owned(),
+            any_holder_regexes: RwLock::new(HashMap::new()),
+            name: name.to_owned(),
+            leniency,
+            year_format: YearFormat::default(),
+            year_list: false,
+            keep_holder: false,
+            sign_spacing: SignSpacing::default(),
         }
     }
 
-    pub fn get_regex(&self, comment_sign: &CommentSign) -> Result<Arc<Regex>, CError> {
-        let c_sign_hash = get_hash(comment_sign);
+    /// Match years in `year_format` instead of four-digit years.
+    pub fn with_year_format(mut self, year_format: YearFormat) -> Self {
+        self.year_format = year_format;
+        self
+    }
+
+    /// Match lists of years like `2019, 2021-2022` instead of a single range.
+    pub fn with_year_list(mut self, year_list: bool) -> Self {
+        self.year_list = year_list;
+        self
+    }
+
+    /// Match copyrights of any holder and capture it as group `holder` if
+    /// `keep_holder` is set, so that only the years of such notes are updated.
+    pub fn with_keep_holder(mut self, keep_holder: bool) -> Self {
+        self.keep_holder = keep_holder;
+        self
+    }
+
+    /// Match `sign_spacing` spaces between comment signs and notes instead of one.
+    pub fn with_sign_spacing(mut self, sign_spacing: SignSpacing) -> Self {
+        self.sign_spacing = sign_spacing;
+        self
+    }
+
+    /// Get the number of spaces between `comment_sign` and notes, to generate
+    /// copyright lines which the regexes of this cache match.
+    pub fn space_after_sign(&self, comment_sign: &CommentSign) -> usize {
+        self.sign_spacing.for_sign(comment_sign)
+    }
 
-        if let Some(regex) = self.regexes.read().unwrap().get(&c_sign_hash) {
-            return Ok(Arc::clone(regex));
+    pub fn get_regex(
+        &self,
+        template: &Template,
+        comment_sign: &CommentSign,
+        copyright_symbol: &str,
+    ) -> Result<Arc<Regex>, CError> {
+        let name_regex = match self.keep_holder {
+            true => HOLDER.to_owned(),
+            false => escape_for_regex(&self.name),
+        };
+        self.get_cached(
+            &self.regexes,
+            &name_regex,
+            template,
+            comment_sign,
+            copyright_symbol,
+        )
+    }
+
+    /// Get a regex matching copyrights with any name instead of ours.
+    pub fn get_any_holder_regex(
+        &self,
+        template: &Template,
+        comment_sign: &CommentSign,
+        copyright_symbol: &str,
+    ) -> Result<Arc<Regex>, CError> {
+        self.get_cached(
+            &self.any_holder_regexes,
+            ANY_NAME,
+            template,
+            comment_sign,
+            copyright_symbol,
+        )
+    }
+
+    fn get_cached(
+        &self,
+        regexes: &RegexMap,
+        name_regex: &str,
+        template: &Template,
+        comment_sign: &CommentSign,
+        copyright_symbol: &str,
+    ) -> Result<Arc<Regex>, CError> {
+        let key = get_hash(&(template, comment_sign, copyright_symbol));
+
+        if let Some(((cached_template, cached_sign, cached_symbol), regex)) =
+            regexes.read().unwrap().get(&key)
+        {
+            // The whole key is stored alongside to not rely on hashes being unique
+            if cached_template == template
+                && cached_sign == comment_sign
+                && cached_symbol == copyright_symbol
+            {
+                return Ok(Arc::clone(regex));
+            }
         }
 
-        log::debug!("Initializing regex for comment sign {:?}", &comment_sign);
-        let regex = Arc::new(generate_comment_regex(&self.base_regex, comment_sign)?);
-        self.regexes
+        log::debug!(
+            "Initializing regex for template {:?}, name {}, comment sign {:?} and copyright symbol {}",
+            template,
+            name_regex,
+            &comment_sign,
+            copyright_symbol
+        );
+        let years_regex = match self.year_list {
+            true => self.year_format.list_regex(),
+            false => self.year_format.regex(),
+        };
+        let base_regex = generate_base_regex_for(
+            template,
+            name_regex,
+            copyright_symbol,
+            self.leniency.text,
+            years_regex,
+        );
+        let regex = Arc::new(generate_comment_regex(
+            &base_regex,
+            comment_sign,
+            &self.leniency,
+            self.space_after_sign(comment_sign),
+        )?);
+        let cached_key = (
+            template.clone(),
+            comment_sign.clone(),
+            copyright_symbol.to_owned(),
+        );
+        regexes
             .write()
             .unwrap()
-            .insert(get_hash(comment_sign), Arc::clone(&regex));
+            .insert(key, (cached_key, Arc::clone(&regex)));
         Ok(regex)
     }
 }
 
-pub fn generate_base_regex(name: &str) -> String {
-    [
-        r"Copyright \(c\)",
+/// Regex for the name in copyrights of any holder.
+const ANY_NAME: &str = ".+?";
+
+/// Regex capturing the name in copyrights of any holder to keep it.
+const HOLDER: &str = "(?P<holder>.+?)";
+
+/// Get the regexes of copyrights in files of `file_type`, an extension or file
+/// name as in the config, e.g. to paste them into a regex tester.
+///
+/// The regex of the comment sign of the file type comes first, followed by
+/// those of equivalent comment signs. Skipped file types have none.
+pub fn regexes_for_file_type(
+    cache: &CopyrightCache,
+    template: &Template,
+    config: &Config,
+    file_type: &str,
+) -> Result<Vec<String>, CError> {
+    // Extensions, names and dotfiles are all looked up from such a file name
+    let filename = format!("file.{}", file_type);
+    let comment_sign = config
+        .get_comment_sign(&filename)
+        .map_err(|_| CError::UnknownCommentSign(file_type.into()))?;
+    if *comment_sign == CommentSign::Skip {
+        return Ok(Vec::new());
+    }
+    let copyright_symbol = config.get_copyright_symbol(&filename);
+    std::iter::once(comment_sign)
+        .chain(config.get_equivalent_comment_signs(&filename))
+        .map(|comment_sign| {
+            cache
+                .get_regex(template, comment_sign, copyright_symbol)
+                .map(|regex| regex.as_str().to_owned())
+        })
+        .collect()
+}
+
+pub fn generate_base_regex(template: &Template, name: &str, copyright_symbol: &str) -> String {
+    generate_base_regex_for(
+        template,
         &escape_for_regex(name),
-        r"(\d{4}(-\d{4}){0,1})",
-    ]
-    .join(" ")
+        copyright_symbol,
+        false,
+        YearFormat::default().regex(),
+    )
+}
+
+fn generate_base_regex_for(
+    template: &Template,
+    name_regex: &str,
+    copyright_symbol: &str,
+    lenient_text: bool,
+    years_regex: &str,
+) -> String {
+    template
+        .parts()
+        .iter()
+        .map(|part| match part {
+            TemplatePart::Literal(text) if lenient_text => make_text_lenient(text),
+            TemplatePart::Literal(text) => escape_for_regex(text),
+            TemplatePart::Name => name_regex.to_owned(),
+            TemplatePart::Years => years_regex.to_owned(),
+            TemplatePart::CurrentYear => r"\d{4}".to_owned(),
+            TemplatePart::CopyrightSymbol => escape_for_regex(copyright_symbol),
+            // Any path if the template was not resolved for a file
+            TemplatePart::File => ANY_NAME.to_owned(),
+        })
+        .collect()
 }
 
 pub async fn generate_copyright_line(
+    template: &Template,
     name: &str,
+    copyright_symbol: &str,
     comment_sign: &CommentSign,
+    space_after_sign: usize,
     years_fut: impl Future<Output = String>,
 ) -> String {
     let years = years_fut.await;
+    let current_year = current_year();
+    let copyright: String = template
+        .parts()
+        .iter()
+        .map(|part| match part {
+            TemplatePart::Literal(text) => text.as_str(),
+            TemplatePart::Name => name,
+            TemplatePart::Years => &years,
+            TemplatePart::CurrentYear => &current_year,
+            TemplatePart::CopyrightSymbol => copyright_symbol,
+            TemplatePart::File => "{file}",
+        })
+        .collect();
+
+    let space = " ".repeat(space_after_sign);
     match comment_sign {
-        CommentSign::LeftOnly(ref left) => [left, "Copyright (c)", name, &years].join(" "),
+        CommentSign::Skip => copyright,
+        CommentSign::LeftOnly(ref left) => [left.as_str(), &copyright].join(&space),
         CommentSign::Enclosing(ref left, ref right) => {
-            [left, "Copyright (c)", name, &years, right].join(" ")
+            [left.as_str(), &copyright, right].join(&space)
         }
     }
 }
 
-fn generate_comment_regex(base_regex: &str, comment_sign: &CommentSign) -> Result<Regex, CError> {
+/// Start of indented copyright lines, the indentation is kept on rewrite.
+const INDENTED: &str = r"^\s*";
+
+/// End of copyright lines, trailing whitespace is tolerated and removed on rewrite.
+const TRAILING: &str = r"\s*$";
+
+/// Generate the regex for a whole copyright line. Unanchored regexes match the
+/// note anywhere in a line, only the matched part is replaced on rewrite.
+fn generate_comment_regex(
+    base_regex: &str,
+    comment_sign: &CommentSign,
+    leniency: &Leniency,
+    space_after_sign: usize,
+) -> Result<Regex, CError> {
+    let space = &" ".repeat(space_after_sign);
+    let (start, end) = match (leniency.unanchored, leniency.indentation) {
+        (true, _) => ("", ""),
+        (false, true) => (INDENTED, TRAILING),
+        (false, false) => ("^", TRAILING),
+    };
     let full_regex_str = match comment_sign {
+        CommentSign::Skip => [start, base_regex, end].join(""),
         CommentSign::LeftOnly(left_sign) => {
-            ["^", &escape_for_regex(&left_sign), " ", base_regex, "$"].join("")
+            [start, &escape_for_regex(left_sign), space, base_regex, end].join("")
         }
+        CommentSign::Enclosing(left_sign, right_sign) if leniency.missing_right_sign => [
+            start,
+            &escape_for_regex(left_sign),
+            space,
+            base_regex,
+            "(?:",
+            space,
+            &escape_for_regex(right_sign),
+            ")?",
+            end,
+        ]
+        .join(""),
         CommentSign::Enclosing(left_sign, right_sign) => [
-            "^",
-            &escape_for_regex(&left_sign),
-            " ",
+            start,
+            &escape_for_regex(left_sign),
+            space,
             base_regex,
-            " ",
-            &escape_for_regex(&right_sign),
-            "$",
+            space,
+            &escape_for_regex(right_sign),
+            end,
         ]
         .join(""),
     };
 
-    Ok(Regex::new(&full_regex_str)?)
+    match leniency.whitespace {
+        true => Ok(Regex::new(&make_whitespace_lenient(&full_regex_str))?),
+        false => Ok(Regex::new(&full_regex_str)?),
+    }
+}
+
+/// Accept any non-empty whitespace where the regex expects spaces.
+///
+/// This works on the full regex since spaces only originate from escaped text.
+fn make_whitespace_lenient(regex_str: &str) -> String {
+    let mut lenient = String::new();
+    let mut chars = regex_str.chars().peekable();
+    while let Some(char) = chars.next() {
+        if char == ' ' {
+            while chars.peek() == Some(&' ') {
+                chars.next();
+            }
+            lenient.push_str(r"\s+");
+        } else {
+            lenient.push(char);
+        }
+    }
+    lenient
+}
+
+/// Match text case-insensitively with its punctuation being optional, e.g.
+/// `All Rights Reserved` for `All rights reserved.`.
+fn make_text_lenient(text: &str) -> String {
+    let lenient: String = text
+        .chars()
+        .map(|char| match char {
+            '.' | ',' | ';' | ':' | '!' => format!("{}?", escape_for_regex(&char.to_string())),
+            other => escape_for_regex(&other.to_string()),
+        })
+        .collect();
+    format!("(?i:{})", lenient)
 }
 
 fn escape_for_regex(text: &str) -> String {
     text.chars()
         .map(|char| match char {
-            '*' => String::from(r"\*"),
-            '.' => String::from(r"\."),
+            '\\' | '.' | '+' | '*' | '?' | '(' | ')' | '|' | '[' | ']' | '{' | '}' | '^' | '$' => {
+                format!(r"\{}", char)
+            }
             other => String::from(other),
         })
         .collect::<Vec<String>>()
         .as_slice()
         .join("")
 }
+
 #[cfg(test)]
 mod test {
 
     use super::escape_for_regex;
-    use super::CommentSign;
-    use super::{generate_base_regex, generate_comment_regex};
+    use super::{generate_base_regex, generate_comment_regex, generate_copyright_line};
+    use super::{generate_base_regex_for, CommentSign, CopyrightCache, Leniency};
+    use super::{regexes_for_file_type, Config};
+    use crate::get_hash;
+    use crate::git_ops::{current_year, YearFormat};
+    use crate::template::{Template, DEFAULT_TEMPLATE};
+    use futures::future::ready;
     use regex::Regex;
+    use std::sync::Arc;
 
     #[test]
     fn test_generate_file_regex() {
         let file_header = "// Copyright (c) DummyCompany Ltd. 2020-2021";
+        let template = Template::parse(DEFAULT_TEMPLATE).unwrap();
         let regex = generate_comment_regex(
-            &generate_base_regex("DummyCompany Ltd."),
+            &generate_base_regex(&template, "DummyCompany Ltd.", "(C)"),
             &CommentSign::LeftOnly("//".into()),
+            &Leniency::default(),
+            1,
         )
         .unwrap();
         assert!(regex.is_match(file_header));
@@ -122,6 +456,8 @@ mod test {
         assert_eq!(escape_for_regex("/*"), r"/\*");
         assert_eq!(escape_for_regex("*/"), r"\*/");
         assert_eq!(escape_for_regex("#"), "#");
+        assert_eq!(escape_for_regex("{Org}"), r"\{Org\}");
+        assert_eq!(escape_for_regex("(c)"), r"\(c\)");
     }
 
     #[test]
@@ -151,10 +487,11 @@ mod test {
     #[test]
     fn test_generate_base_regex() {
         let name = "DummyCompany Ltd.";
-        let base_regex = generate_base_regex(name);
+        let template = Template::parse(DEFAULT_TEMPLATE).unwrap();
+        let base_regex = generate_base_regex(&template, name, "(C)");
         assert_eq!(
             base_regex,
-            r"Copyright \(c\) DummyCompany Ltd\. (\d{4}(-\d{4}){0,1})"
+            r"Copyright \(c\) DummyCompany Ltd\. (?P<years>\d{4}(-\d{4}){0,1})"
         );
     }
 
@@ -181,4 +518,445 @@ mod test {
             assert!(!copyright_re.is_match(example));
         }
     }
+
+    #[test]
+    fn test_cache_compares_whole_key() {
+        let template = Template::parse(DEFAULT_TEMPLATE).unwrap();
+        let comment_sign = CommentSign::LeftOnly("//".into());
+        let cache = CopyrightCache::new("Org", Leniency::default());
+
+        // Simulate a hash collision with a regex of another comment sign and symbol
+        let key = get_hash(&(&template, &comment_sign, "(C)"));
+        let other_regex = Arc::new(Regex::new("^# Copyright").unwrap());
+        for other in [
+            (CommentSign::LeftOnly("#".into()), "(C)"),
+            (comment_sign.clone(), "©"),
+        ] {
+            let cached_key = (template.clone(), other.0, other.1.to_owned());
+            cache
+                .regexes
+                .write()
+                .unwrap()
+                .insert(key, (cached_key, Arc::clone(&other_regex)));
+
+            let regex = cache.get_regex(&template, &comment_sign, "(C)").unwrap();
+            assert!(regex.is_match("// Copyright (c) Org 2020"));
+            assert!(!regex.is_match("# Copyright (c) Org 2020"));
+        }
+    }
+
+    #[test]
+    fn test_regexes_for_file_type() {
+        let config = Config::default();
+        let template = Template::parse(DEFAULT_TEMPLATE).unwrap();
+        let cache = CopyrightCache::new("Org", Leniency::default());
+        assert_eq!(
+            regexes_for_file_type(&cache, &template, &config, "rs").unwrap(),
+            vec![r"^// Copyright \(c\) Org (?P<years>\d{4}(-\d{4}){0,1})\s*$"]
+        );
+        assert_eq!(
+            regexes_for_file_type(&cache, &template, &config, "main.css").unwrap(),
+            vec![r"^/\* Copyright \(c\) Org (?P<years>\d{4}(-\d{4}){0,1}) \*/\s*$"]
+        );
+        let err = regexes_for_file_type(&cache, &template, &config, "md").unwrap_err();
+        assert_eq!(err.to_string(), "No comment sign found for file md");
+
+        let config = Config::from_str(
+            r##"
+comment_sign_map:
+  md: ~
+ignore_files: []
+ignore_dirs: []
+"##,
+        )
+        .unwrap();
+        assert!(regexes_for_file_type(&cache, &template, &config, "md")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_template_with_literal_braces() {
+        let template = Template::parse("Copyright {years} {{Org}}").unwrap();
+        let comment_sign = CommentSign::LeftOnly("//".into());
+
+        let line =
+            generate_copyright_line(&template, "", "(C)", &comment_sign, 1, ready("2021".into()))
+                .await;
+        assert_eq!(line, "// Copyright 2021 {Org}");
+
+        let regex = generate_comment_regex(
+            &generate_base_regex(&template, "", "(C)"),
+            &comment_sign,
+            &Leniency::default(),
+            1,
+        )
+        .unwrap();
+        let cap = regex.captures(&line).unwrap();
+        assert_eq!(&cap[1], "2021");
+        assert!(!regex.is_match("// Copyright 2021 Org"));
+    }
+
+    #[tokio::test]
+    async fn test_current_year() {
+        let template = Template::parse("Copyright 2015-{current_year} {name}").unwrap();
+        let cache = CopyrightCache::new("Org", Leniency::default());
+        let comment_sign = CommentSign::LeftOnly("#".into());
+
+        let line = generate_copyright_line(
+            &template,
+            "Org",
+            "(C)",
+            &comment_sign,
+            1,
+            ready("2021".into()),
+        )
+        .await;
+        assert_eq!(line, format!("# Copyright 2015-{} Org", current_year()));
+
+        let regex = cache.get_regex(&template, &comment_sign, "(C)").unwrap();
+        assert!(regex.is_match(&line));
+        assert!(regex.is_match("# Copyright 2015-2019 Org"));
+        assert!(!regex.is_match("# Copyright 2015 Org"));
+    }
+
+    #[tokio::test]
+    async fn test_copyright_symbol_per_extension() {
+        let template = Template::parse("Copyright {copyright_symbol} {years} {name}").unwrap();
+        let cache = CopyrightCache::new("Org", Leniency::default());
+        let comment_sign = CommentSign::LeftOnly("#".into());
+
+        let code_line = generate_copyright_line(
+            &template,
+            "Org",
+            "(C)",
+            &comment_sign,
+            1,
+            ready("2021".into()),
+        )
+        .await;
+        let docs_line = generate_copyright_line(
+            &template,
+            "Org",
+            "©",
+            &comment_sign,
+            1,
+            ready("2021".into()),
+        )
+        .await;
+        assert_eq!(code_line, "# Copyright (C) 2021 Org");
+        assert_eq!(docs_line, "# Copyright © 2021 Org");
+
+        let code_regex = cache.get_regex(&template, &comment_sign, "(C)").unwrap();
+        let docs_regex = cache.get_regex(&template, &comment_sign, "©").unwrap();
+        assert!(code_regex.is_match(&code_line));
+        assert!(!code_regex.is_match(&docs_line));
+        assert!(docs_regex.is_match(&docs_line));
+        assert!(!docs_regex.is_match(&code_line));
+    }
+
+    #[test]
+    fn test_cache_distinguishes_templates() {
+        let cache = CopyrightCache::new("Org", Leniency::default());
+        let comment_sign = CommentSign::LeftOnly("//".into());
+        let template = Template::parse(DEFAULT_TEMPLATE).unwrap();
+        let other_template = Template::parse("{name} (c) {years}").unwrap();
+
+        let regex = cache.get_regex(&template, &comment_sign, "(C)").unwrap();
+        let other_regex = cache
+            .get_regex(&other_template, &comment_sign, "(C)")
+            .unwrap();
+        assert_ne!(regex.as_str(), other_regex.as_str());
+        assert!(regex.is_match("// Copyright (c) Org 2020"));
+        assert!(other_regex.is_match("// Org (c) 2020"));
+
+        let cached_regex = cache.get_regex(&template, &comment_sign, "(C)").unwrap();
+        assert!(std::sync::Arc::ptr_eq(&regex, &cached_regex));
+    }
+
+    #[test]
+    fn test_any_holder_regex() {
+        let cache = CopyrightCache::new("Org", Leniency::default());
+        let comment_sign = CommentSign::LeftOnly("#".into());
+        let template = Template::parse(DEFAULT_TEMPLATE).unwrap();
+
+        let regex = cache.get_regex(&template, &comment_sign, "(C)").unwrap();
+        let any_holder = cache
+            .get_any_holder_regex(&template, &comment_sign, "(C)")
+            .unwrap();
+        assert!(!regex.is_match("# Copyright (c) Jane Doe 2019"));
+        assert_eq!(
+            &any_holder
+                .captures("# Copyright (c) Jane Doe 2019")
+                .unwrap()[1],
+            "2019"
+        );
+        assert!(any_holder.is_match("# Copyright (c) Org 2020"));
+        assert!(!any_holder.is_match("# Copyright (c)  2020"));
+    }
+
+    #[test]
+    fn test_lenient_whitespace() {
+        let template = Template::parse(DEFAULT_TEMPLATE).unwrap();
+        let base_regex = generate_base_regex(&template, "DummyCompany  Ltd.", "(C)");
+        let comment_sign = CommentSign::Enclosing("/*".into(), "*/".into());
+        let strict =
+            generate_comment_regex(&base_regex, &comment_sign, &Leniency::default(), 1).unwrap();
+        let leniency = Leniency {
+            whitespace: true,
+            ..Default::default()
+        };
+        let lenient = generate_comment_regex(&base_regex, &comment_sign, &leniency, 1).unwrap();
+
+        let irregular = [
+            "/*  Copyright (c) DummyCompany Ltd. 2020 */",
+            "/* Copyright  (c)\tDummyCompany Ltd.   2020 */",
+            "/* Copyright (c) DummyCompany Ltd. 2020 */  ",
+        ];
+        for line in irregular {
+            assert!(!strict.is_match(line));
+            assert_eq!(&lenient.captures(line).unwrap()[1], "2020");
+        }
+        assert!(!lenient.is_match("/* Copyright (c) DummyCompanyLtd. 2020 */"));
+    }
+
+    #[test]
+    fn test_trailing_whitespace() {
+        let template = Template::parse(DEFAULT_TEMPLATE).unwrap();
+        let regex = generate_comment_regex(
+            &generate_base_regex(&template, "DummyCompany", "(C)"),
+            &CommentSign::LeftOnly("//".into()),
+            &Leniency::default(),
+            1,
+        )
+        .unwrap();
+
+        for line in [
+            "// Copyright (c) DummyCompany 2020",
+            "// Copyright (c) DummyCompany 2020 ",
+            "// Copyright (c) DummyCompany 2020\t ",
+        ] {
+            assert_eq!(&regex.captures(line).unwrap()[1], "2020");
+        }
+        assert!(!regex.is_match("// Copyright (c) DummyCompany 2020 and more"));
+    }
+
+    #[test]
+    fn test_lenient_text() {
+        let template = Template::parse("Copyright {name} {years}. All rights reserved.").unwrap();
+        let comment_sign = CommentSign::LeftOnly("//".into());
+        let regex = generate_comment_regex(
+            &generate_base_regex_for(&template, "Org", "(C)", true, YearFormat::default().regex()),
+            &comment_sign,
+            &Leniency::default(),
+            1,
+        )
+        .unwrap();
+
+        let variants = [
+            "// Copyright Org 2020. All rights reserved.",
+            "// Copyright Org 2020. All Rights Reserved",
+            "// COPYRIGHT Org 2020 all rights reserved.",
+        ];
+        for line in variants {
+            assert_eq!(&regex.captures(line).unwrap()[1], "2020");
+        }
+        assert!(!regex.is_match("# Copyright Org 2020. All rights reserved."));
+        assert!(!regex.is_match("// Copyright Org 2020. Some rights reserved."));
+        assert!(!regex.is_match("// Copyright Other 2020. All rights reserved."));
+    }
+
+    #[test]
+    fn test_unanchored() {
+        let template = Template::parse(DEFAULT_TEMPLATE).unwrap();
+        let base_regex = generate_base_regex(&template, "Org", "(C)");
+        let comment_sign = CommentSign::Enclosing("/*".into(), "*/".into());
+        let anchored =
+            generate_comment_regex(&base_regex, &comment_sign, &Leniency::default(), 1).unwrap();
+        let leniency = Leniency {
+            unanchored: true,
+            ..Default::default()
+        };
+        let unanchored = generate_comment_regex(&base_regex, &comment_sign, &leniency, 1).unwrap();
+
+        let line = "{/* Copyright (c) Org 2019-2021 */}";
+        assert!(!anchored.is_match(line));
+        let cap = unanchored.captures(line).unwrap();
+        assert_eq!(&cap[1], "2019-2021");
+        assert_eq!(&cap[0], "/* Copyright (c) Org 2019-2021 */");
+    }
+
+    #[test]
+    fn test_indented() {
+        let template = Template::parse(DEFAULT_TEMPLATE).unwrap();
+        let base_regex = generate_base_regex(&template, "Org", "(C)");
+        let comment_sign = CommentSign::LeftOnly("//".into());
+        let strict =
+            generate_comment_regex(&base_regex, &comment_sign, &Leniency::default(), 1).unwrap();
+        let leniency = Leniency {
+            indentation: true,
+            ..Default::default()
+        };
+        let indented = generate_comment_regex(&base_regex, &comment_sign, &leniency, 1).unwrap();
+
+        for line in [
+            "    // Copyright (c) Org 2020",
+            "\t// Copyright (c) Org 2020",
+        ] {
+            assert!(!strict.is_match(line));
+            assert_eq!(&indented.captures(line).unwrap()[1], "2020");
+        }
+        for regex in [&strict, &indented] {
+            assert_eq!(
+                &regex.captures("// Copyright (c) Org 2020").unwrap()[1],
+                "2020"
+            );
+            assert!(!regex.is_match("x // Copyright (c) Org 2020"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_two_digit_years() {
+        let template = Template::parse(DEFAULT_TEMPLATE).unwrap();
+        let comment_sign = CommentSign::LeftOnly("#".into());
+        let regex = CopyrightCache::new("Org", Leniency::default())
+            .with_year_format(YearFormat::TwoDigit)
+            .get_regex(&template, &comment_sign, "(C)")
+            .unwrap();
+
+        assert_eq!(
+            &regex.captures("# Copyright (c) Org '19-'21").unwrap()[1],
+            "'19-'21"
+        );
+        assert_eq!(
+            &regex.captures("# Copyright (c) Org '19").unwrap()[1],
+            "'19"
+        );
+        assert!(!regex.is_match("# Copyright (c) Org 2019-2021"));
+
+        let line = generate_copyright_line(
+            &template,
+            "Org",
+            "(C)",
+            &comment_sign,
+            1,
+            ready(YearFormat::TwoDigit.format("2019-2021")),
+        )
+        .await;
+        assert!(regex.is_match(&line));
+    }
+
+    #[test]
+    fn test_year_list() {
+        let template = Template::parse(DEFAULT_TEMPLATE).unwrap();
+        let comment_sign = CommentSign::LeftOnly("#".into());
+        let regex = CopyrightCache::new("Org", Leniency::default())
+            .with_year_list(true)
+            .get_regex(&template, &comment_sign, "(C)")
+            .unwrap();
+        for years in ["2019", "2019-2021", "2019, 2021", "2015, 2019-2021, 2023"] {
+            let line = format!("# Copyright (c) Org {}", years);
+            assert_eq!(&regex.captures(&line).unwrap()["years"], years);
+        }
+        assert!(!regex.is_match("# Copyright (c) Org 2019,2021"));
+
+        let regex = CopyrightCache::new("Org", Leniency::default())
+            .get_regex(&template, &comment_sign, "(C)")
+            .unwrap();
+        assert!(!regex.is_match("# Copyright (c) Org 2019, 2021"));
+    }
+
+    #[test]
+    fn test_keep_holder() {
+        let template = Template::parse(DEFAULT_TEMPLATE).unwrap();
+        let comment_sign = CommentSign::LeftOnly("#".into());
+        let regex = CopyrightCache::new("Org", Leniency::default())
+            .with_keep_holder(true)
+            .get_regex(&template, &comment_sign, "(C)")
+            .unwrap();
+
+        let cap = regex.captures("# Copyright (c) Jane Doe 2019").unwrap();
+        assert_eq!(&cap["holder"], "Jane Doe");
+        assert_eq!(&cap["years"], "2019");
+        let cap = regex
+            .captures("# Copyright (c) Other Corp. 2019-2020")
+            .unwrap();
+        assert_eq!(&cap["holder"], "Other Corp.");
+        assert_eq!(&cap["years"], "2019-2020");
+    }
+
+    #[test]
+    fn test_missing_right_sign() {
+        let template = Template::parse(DEFAULT_TEMPLATE).unwrap();
+        let base_regex = generate_base_regex(&template, "Org", "(C)");
+        let comment_sign = CommentSign::Enclosing("<!--".into(), "-->".into());
+        let strict =
+            generate_comment_regex(&base_regex, &comment_sign, &Leniency::default(), 1).unwrap();
+        let leniency = Leniency {
+            missing_right_sign: true,
+            ..Default::default()
+        };
+        let lenient = generate_comment_regex(&base_regex, &comment_sign, &leniency, 1).unwrap();
+
+        assert!(!strict.is_match("<!-- Copyright (c) Org 2019-2020"));
+        for line in [
+            "<!-- Copyright (c) Org 2019-2020",
+            "<!-- Copyright (c) Org 2019-2020 -->",
+        ] {
+            assert_eq!(&lenient.captures(line).unwrap()["years"], "2019-2020");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sign_spacing() {
+        let cfg = Config::from_str(
+            r##"
+comment_sign_map:
+  rs: "//"
+  css: ["/*", "*/"]
+  py: "#"
+space_after_sign: 0
+space_after_sign_map:
+  "#": 1
+ignore_files: []
+ignore_dirs: []
+"##,
+        )
+        .unwrap();
+        let template = Template::parse(DEFAULT_TEMPLATE).unwrap();
+        let cache =
+            CopyrightCache::new("Org", Leniency::default()).with_sign_spacing(cfg.sign_spacing());
+
+        for (filename, expected) in [
+            ("main.rs", "//Copyright (c) Org 2021"),
+            ("style.css", "/*Copyright (c) Org 2021*/"),
+            ("main.py", "# Copyright (c) Org 2021"),
+        ] {
+            let comment_sign = cfg.get_comment_sign(filename).unwrap();
+            let line = generate_copyright_line(
+                &template,
+                "Org",
+                "(C)",
+                comment_sign,
+                cache.space_after_sign(comment_sign),
+                ready("2021".into()),
+            )
+            .await;
+            assert_eq!(line, expected);
+
+            let regex = cache.get_regex(&template, comment_sign, "(C)").unwrap();
+            let cap = regex.captures(&line).unwrap();
+            assert_eq!(&cap[1], "2021");
+        }
+
+        // Notes are only detected with the configured spacing
+        let comment_sign = CommentSign::LeftOnly("//".into());
+        let regex = cache.get_regex(&template, &comment_sign, "(C)").unwrap();
+        assert!(!regex.is_match("// Copyright (c) Org 2021"));
+        let regex = CopyrightCache::new("Org", Leniency::default())
+            .get_regex(&template, &comment_sign, "(C)")
+            .unwrap();
+        assert!(regex.is_match("// Copyright (c) Org 2021"));
+        assert!(!regex.is_match("//Copyright (c) Org 2021"));
+    }
 }
@@ -0,0 +1,83 @@
+//! Remember the template of the last run in the git directory of the repository.
+//!
+//! A changed template rewrites the copyrights of all files, which is refused
+//! unless confirmed, e.g. to not rewrite everything due to a typo. The state
+//! is kept out of the working tree, so runs do not leave untracked files.
+
+use crate::CError;
+use std::path::Path;
+
+/// File in the git directory with the hash of the template of the last run.
+pub const STATE_FILE: &str = "git_copyright_state";
+
+const TEMPLATE_HASH_KEY: &str = "template_hash: ";
+
+/// Hash a template with FNV-1a, which unlike the hasher of the standard
+/// library is stable across versions and platforms.
+pub fn template_hash(template: &str) -> String {
+    let hash = template.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+/// Read the template hash of the last run, `None` if there was none.
+fn read_template_hash(git_dir: &Path) -> Result<Option<String>, CError> {
+    let state_path = git_dir.join(STATE_FILE);
+    if !state_path.is_file() {
+        return Ok(None);
+    }
+    let state = std::fs::read_to_string(&state_path)
+        .map_err(|_| CError::ReadError(state_path.display().to_string()))?;
+    Ok(state
+        .lines()
+        .find_map(|line| line.strip_prefix(TEMPLATE_HASH_KEY))
+        .map(|hash| hash.trim().to_owned()))
+}
+
+/// Fail if the template changed since the last run unless `confirmed`.
+pub fn check_template_state(git_dir: &Path, hash: &str, confirmed: bool) -> Result<(), CError> {
+    match read_template_hash(git_dir)? {
+        Some(last_hash) if last_hash != hash && !confirmed => Err(CError::TemplateChanged),
+        Some(last_hash) if last_hash != hash => {
+            log::info!("Template changed since the last run, reformatting confirmed");
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn write_template_state(git_dir: &Path, hash: &str) -> Result<(), CError> {
+    let state_path = git_dir.join(STATE_FILE);
+    std::fs::write(&state_path, format!("{}{}\n", TEMPLATE_HASH_KEY, hash))
+        .map_err(|_| CError::WriteError(state_path.display().to_string()))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{check_template_state, template_hash, write_template_state};
+    use crate::CError;
+
+    #[test]
+    fn test_template_state() {
+        assert_eq!(template_hash(""), "cbf29ce484222325");
+        assert_ne!(
+            template_hash("Copyright (c) {name} {years}"),
+            template_hash("Copyright {name} {years}")
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let hash = template_hash("Copyright (c) {name} {years}");
+        let other_hash = template_hash("Copyright {name} {years}");
+        check_template_state(dir.path(), &other_hash, false).unwrap();
+
+        write_template_state(dir.path(), &hash).unwrap();
+        check_template_state(dir.path(), &hash, false).unwrap();
+        assert!(matches!(
+            check_template_state(dir.path(), &other_hash, false),
+            Err(CError::TemplateChanged)
+        ));
+        check_template_state(dir.path(), &other_hash, true).unwrap();
+    }
+}
@@ -0,0 +1,269 @@
+//! Aggregate per-file results.
+
+use crate::file_ops::FileStatus;
+use crate::CError;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Component, Path};
+
+/// Counts of files which need changes or failed within a directory.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DirSummary {
+    pub updated: usize,
+    pub added: usize,
+    pub errored: usize,
+}
+
+/// Group results by the top-level directory of the files.
+///
+/// Files in the repository root are grouped under `.`.
+pub fn summarize_by_dir<'a>(
+    results: impl Iterator<Item = (&'a str, &'a Result<FileStatus, CError>)>,
+) -> BTreeMap<String, DirSummary> {
+    let mut summaries: BTreeMap<String, DirSummary> = BTreeMap::new();
+    for (filepath, result) in results {
+        let summary = summaries.entry(top_level_dir(filepath)).or_default();
+        match result {
+            Ok(FileStatus::Updated { .. })
+            | Ok(FileStatus::Reformatted { .. })
+            | Ok(FileStatus::Removed { .. }) => summary.updated += 1,
+            Ok(FileStatus::Added { .. }) => summary.added += 1,
+            Ok(FileStatus::Unchanged) | Ok(FileStatus::Skipped) => (),
+            Err(_) => summary.errored += 1,
+        }
+    }
+    summaries
+}
+
+pub fn write_dir_summary(
+    summaries: &BTreeMap<String, DirSummary>,
+    report: &mut dyn Write,
+) -> Result<(), CError> {
+    writeln!(report, "Summary by directory (updated/added/errored):")?;
+    for (dir, summary) in summaries.iter() {
+        writeln!(
+            report,
+            "{}: {}/{}/{}",
+            dir, summary.updated, summary.added, summary.errored
+        )?;
+    }
+    Ok(())
+}
+
+/// Number of files which need a change and of lines these changes add.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ChangeEstimate {
+    pub files: usize,
+    pub added_lines: usize,
+}
+
+/// Estimate the changes of the results, an added copyright adds one line
+/// while updated ones are changed in place.
+pub fn estimate_changes<'a>(
+    results: impl Iterator<Item = &'a Result<FileStatus, CError>>,
+) -> ChangeEstimate {
+    let mut estimate = ChangeEstimate::default();
+    for result in results {
+        match result {
+            Ok(FileStatus::Added { .. }) => {
+                estimate.files += 1;
+                estimate.added_lines += 1;
+            }
+            Ok(status) if status.needs_change() => estimate.files += 1,
+            _ => (),
+        }
+    }
+    estimate
+}
+
+/// Write a compliance report of all files with counts per status, followed
+/// by the files which are not compliant sorted by path.
+pub fn write_audit_report<'a>(
+    results: impl Iterator<Item = (&'a str, &'a Result<FileStatus, CError>)>,
+    report: &mut dyn Write,
+) -> Result<(), CError> {
+    let (mut files, mut compliant, mut skipped) = (0, 0, 0);
+    let mut findings: Vec<(&str, &str, String)> = Vec::new();
+    for (filepath, result) in results {
+        files += 1;
+        match result {
+            Ok(FileStatus::Unchanged) => compliant += 1,
+            Ok(FileStatus::Skipped) => skipped += 1,
+            Ok(FileStatus::Updated { found_years, .. }) => {
+                findings.push((filepath, "outdated", found_years.clone()))
+            }
+            Ok(FileStatus::Reformatted { .. }) => {
+                findings.push((filepath, "outdated", "format".into()))
+            }
+            Ok(FileStatus::Removed { .. }) => {
+                findings.push((filepath, "outdated", "remove".into()))
+            }
+            Ok(FileStatus::Added { .. }) => findings.push((filepath, "missing", String::new())),
+            Err(e) => findings.push((filepath, "errored", e.to_string())),
+        }
+    }
+    let count = |kind: &str| findings.iter().filter(|(_, k, _)| *k == kind).count();
+    writeln!(
+        report,
+        "Audited {} files: {} compliant, {} outdated, {} missing, {} errored, {} skipped",
+        files,
+        compliant,
+        count("outdated"),
+        count("missing"),
+        count("errored"),
+        skipped
+    )?;
+    findings.sort();
+    for (filepath, kind, detail) in findings {
+        match detail.is_empty() {
+            true => writeln!(report, "{}: {}", kind, filepath)?,
+            false => writeln!(report, "{}: {} ({})", kind, filepath, detail)?,
+        }
+    }
+    Ok(())
+}
+
+fn top_level_dir(filepath: &str) -> String {
+    let mut components = Path::new(filepath)
+        .components()
+        .filter(|c| !matches!(c, Component::CurDir));
+    match (components.next(), components.next()) {
+        (Some(first), Some(_)) => first.as_os_str().to_string_lossy().into_owned(),
+        _ => ".".into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{estimate_changes, summarize_by_dir, write_audit_report, write_dir_summary};
+    use super::{ChangeEstimate, DirSummary};
+    use crate::file_ops::FileStatus;
+    use crate::CError;
+
+    #[test]
+    fn test_summarize_by_dir() {
+        let added = || {
+            Ok(FileStatus::Added {
+                years: "2020".into(),
+            })
+        };
+        let results: Vec<(&str, Result<FileStatus, CError>)> = vec![
+            ("backend/src/main.rs", added()),
+            (
+                "backend/lib.rs",
+                Ok(FileStatus::Updated {
+                    line_nr: 0,
+                    found_years: "2019".into(),
+                    years: "2020".into(),
+                }),
+            ),
+            ("frontend/app.ts", Ok(FileStatus::Unchanged)),
+            (
+                "frontend/x.unknown",
+                Err(CError::UnknownCommentSign("x".into())),
+            ),
+            ("setup.py", added()),
+            ("./build.rs", added()),
+        ];
+
+        let summaries = summarize_by_dir(results.iter().map(|(f, r)| (*f, r)));
+        assert_eq!(
+            summaries.get("backend"),
+            Some(&DirSummary {
+                updated: 1,
+                added: 1,
+                errored: 0
+            })
+        );
+        assert_eq!(
+            summaries.get("frontend"),
+            Some(&DirSummary {
+                updated: 0,
+                added: 0,
+                errored: 1
+            })
+        );
+        assert_eq!(
+            summaries.get("."),
+            Some(&DirSummary {
+                updated: 0,
+                added: 2,
+                errored: 0
+            })
+        );
+
+        let mut report = Vec::new();
+        write_dir_summary(&summaries, &mut report).unwrap();
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            "Summary by directory (updated/added/errored):\n.: 0/2/0\nbackend: 1/1/0\nfrontend: 0/0/1\n"
+        );
+    }
+
+    #[test]
+    fn test_estimate_changes() {
+        let results: Vec<Result<FileStatus, CError>> = vec![
+            Ok(FileStatus::Added {
+                years: "2020".into(),
+            }),
+            Ok(FileStatus::Added {
+                years: "2021".into(),
+            }),
+            Ok(FileStatus::Updated {
+                line_nr: 0,
+                found_years: "2019".into(),
+                years: "2020".into(),
+            }),
+            Ok(FileStatus::Reformatted {
+                line_nr: 1,
+                copyright_line: "# Copyright 2020".into(),
+            }),
+            Ok(FileStatus::Unchanged),
+            Ok(FileStatus::Skipped),
+            Err(CError::UnknownCommentSign("x".into())),
+        ];
+        assert_eq!(
+            estimate_changes(results.iter()),
+            ChangeEstimate {
+                files: 4,
+                added_lines: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_write_audit_report() {
+        let results: Vec<(&str, Result<FileStatus, CError>)> = vec![
+            ("src/main.rs", Ok(FileStatus::Unchanged)),
+            (
+                "src/lib.rs",
+                Ok(FileStatus::Updated {
+                    line_nr: 0,
+                    found_years: "2019".into(),
+                    years: "2020".into(),
+                }),
+            ),
+            (
+                "build.rs",
+                Ok(FileStatus::Added {
+                    years: "2020".into(),
+                }),
+            ),
+            (
+                "x.unknown",
+                Err(CError::UnknownCommentSign("x.unknown".into())),
+            ),
+            ("README.md", Ok(FileStatus::Skipped)),
+        ];
+        let mut report = Vec::new();
+        write_audit_report(results.iter().map(|(f, r)| (*f, r)), &mut report).unwrap();
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            "Audited 5 files: 1 compliant, 1 outdated, 1 missing, 1 errored, 1 skipped\n\
+             missing: build.rs\n\
+             outdated: src/lib.rs (2019)\n\
+             errored: x.unknown (No comment sign found for file x.unknown)\n"
+        );
+    }
+}
@@ -0,0 +1,248 @@
+//! Parse copyright templates.
+//!
+//! A template describes the copyright note without comment signs, e.g.
+//! `Copyright (c) {name} {years}`. Placeholders are enclosed in braces. Literal
+//! braces can be written as `{{` and `}}`.
+//!
+//! Supported placeholders are `{name}`, `{years}`, `{current_year}`,
+//! `{copyright_symbol}` and `{file}`. The copyright symbol is looked up per file
+//! extension in the configuration. `{current_year}` is the year of the clock
+//! rather than of the history, e.g. for notices like
+//! `Copyright 2015-{current_year} {name}`. `{file}` is the path of the file
+//! relative to the repository, see `Template::for_file`.
+//! `{years}` is required unless the template has `{current_year}` or is
+//! explicitly parsed as yearless, e.g. for notices like
+//! `Copyright {name}. All rights reserved.`.
+
+use crate::CError;
+use std::borrow::Cow;
+
+pub const DEFAULT_TEMPLATE: &str = "Copyright (c) {name} {years}";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TemplatePart {
+    Literal(String),
+    Name,
+    Years,
+    CurrentYear,
+    CopyrightSymbol,
+    File,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Template {
+    parts: Vec<TemplatePart>,
+}
+
+impl Template {
+    /// Parse a template which contains `{years}` exactly once, or at most once
+    /// if it contains `{current_year}`.
+    pub fn parse(template: &str) -> Result<Self, CError> {
+        Self::parse_with(template, true)
+    }
+
+    /// Parse a template which contains `{years}` at most once.
+    pub fn parse_yearless(template: &str) -> Result<Self, CError> {
+        Self::parse_with(template, false)
+    }
+
+    fn parse_with(template: &str, years_required: bool) -> Result<Self, CError> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(char) = chars.next() {
+            match char {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(other) => placeholder.push(other),
+                            None => {
+                                return Err(CError::TemplateError(format!(
+                                    "Unclosed placeholder in '{}'",
+                                    template
+                                )))
+                            }
+                        }
+                    }
+
+                    if !literal.is_empty() {
+                        parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(match placeholder.as_str() {
+                        "name" => TemplatePart::Name,
+                        "years" => TemplatePart::Years,
+                        "current_year" => TemplatePart::CurrentYear,
+                        "copyright_symbol" => TemplatePart::CopyrightSymbol,
+                        "file" => TemplatePart::File,
+                        other => {
+                            return Err(CError::TemplateError(format!(
+                                "Unknown placeholder {{{}}}, use {{{{ and }}}} for literal braces",
+                                other
+                            )))
+                        }
+                    });
+                }
+                '}' => {
+                    return Err(CError::TemplateError(format!(
+                        "Unmatched '}}' in '{}', use }}}} for a literal brace",
+                        template
+                    )))
+                }
+                other => literal.push(other),
+            }
+        }
+
+        if !literal.is_empty() {
+            parts.push(TemplatePart::Literal(literal));
+        }
+
+        let years_required = years_required && !parts.contains(&TemplatePart::CurrentYear);
+        match parts.iter().filter(|p| **p == TemplatePart::Years).count() {
+            1 => Ok(Template { parts }),
+            0 if !years_required => Ok(Template { parts }),
+            _ if !years_required => Err(CError::TemplateError(format!(
+                "'{}' must contain the placeholder {{years}} at most once",
+                template
+            ))),
+            _ => Err(CError::TemplateError(format!(
+                "'{}' must contain the placeholder {{years}} exactly once",
+                template
+            ))),
+        }
+    }
+
+    pub fn parts(&self) -> &[TemplatePart] {
+        &self.parts
+    }
+
+    /// Check if copyrights of this template contain years.
+    pub fn has_years(&self) -> bool {
+        self.parts.contains(&TemplatePart::Years)
+    }
+
+    /// Resolve `{file}` to the literal `filepath`.
+    ///
+    /// Templates with `{file}` thereby differ per file, so that the regexes of
+    /// every file are cached separately. Other templates are borrowed as they are.
+    pub fn for_file(&self, filepath: &str) -> Cow<'_, Template> {
+        if !self.parts.contains(&TemplatePart::File) {
+            return Cow::Borrowed(self);
+        }
+        let parts = self
+            .parts
+            .iter()
+            .map(|part| match part {
+                TemplatePart::File => TemplatePart::Literal(filepath.to_owned()),
+                other => other.clone(),
+            })
+            .collect();
+        Cow::Owned(Template { parts })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{Template, TemplatePart, DEFAULT_TEMPLATE};
+    use std::borrow::Cow;
+
+    #[test]
+    fn test_parse_default_template() {
+        let template = Template::parse(DEFAULT_TEMPLATE).unwrap();
+        assert_eq!(
+            template.parts(),
+            &[
+                TemplatePart::Literal("Copyright (c) ".into()),
+                TemplatePart::Name,
+                TemplatePart::Literal(" ".into()),
+                TemplatePart::Years,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_literal_braces() {
+        let template = Template::parse("Copyright {years} {{Org}}").unwrap();
+        assert_eq!(
+            template.parts(),
+            &[
+                TemplatePart::Literal("Copyright ".into()),
+                TemplatePart::Years,
+                TemplatePart::Literal(" {Org}".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_copyright_symbol() {
+        let template = Template::parse("Copyright {copyright_symbol} {years}").unwrap();
+        assert_eq!(template.parts()[1], TemplatePart::CopyrightSymbol);
+    }
+
+    #[test]
+    fn test_parse_current_year() {
+        let template = Template::parse("Copyright 2015-{current_year} {name}").unwrap();
+        assert!(!template.has_years());
+        assert_eq!(
+            template.parts(),
+            &[
+                TemplatePart::Literal("Copyright 2015-".into()),
+                TemplatePart::CurrentYear,
+                TemplatePart::Literal(" ".into()),
+                TemplatePart::Name,
+            ]
+        );
+        assert!(Template::parse("Copyright {years}-{current_year} {name}").is_ok());
+        assert!(Template::parse("Copyright {years} {years} {current_year}").is_err());
+    }
+
+    #[test]
+    fn test_for_file() {
+        let template = Template::parse("File: {file} - Copyright {name} {years}").unwrap();
+        assert_eq!(template.parts()[1], TemplatePart::File);
+        let resolved = template.for_file("src/foo.rs");
+        assert_eq!(
+            resolved.parts()[..3],
+            [
+                TemplatePart::Literal("File: ".into()),
+                TemplatePart::Literal("src/foo.rs".into()),
+                TemplatePart::Literal(" - Copyright ".into()),
+            ]
+        );
+        assert_ne!(*resolved, *template.for_file("src/bar.rs"));
+
+        let template = Template::parse(DEFAULT_TEMPLATE).unwrap();
+        assert!(matches!(template.for_file("src/foo.rs"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_parse_invalid_templates() {
+        assert!(Template::parse("Copyright {Org} {years}").is_err());
+        assert!(Template::parse("Copyright {years").is_err());
+        assert!(Template::parse("Copyright {years} Org}").is_err());
+        assert!(Template::parse("Copyright {name}").is_err());
+        assert!(Template::parse("Copyright {years} {years}").is_err());
+    }
+
+    #[test]
+    fn test_parse_yearless() {
+        let template = Template::parse_yearless("Copyright {name}. All rights reserved.").unwrap();
+        assert!(!template.has_years());
+        assert_eq!(template.parts()[1], TemplatePart::Name);
+        assert!(Template::parse_yearless(DEFAULT_TEMPLATE)
+            .unwrap()
+            .has_years());
+        assert!(Template::parse_yearless("Copyright {years} {years}").is_err());
+    }
+}
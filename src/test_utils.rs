@@ -0,0 +1,74 @@
+//! Helpers to set up throwaway repositories in tests.
+
+use crate::Config;
+use std::path::Path;
+use std::process::Command;
+use tempfile::TempDir;
+
+pub fn init_default_config() {
+    Config::assign_for_tests();
+}
+
+pub fn git(repo: &Path, args: &[&str]) {
+    git_at(repo, args, "2020-06-01T12:00:00");
+}
+
+/// Run a git command with author and committer date fixed to `date`.
+pub fn git_at(repo: &Path, args: &[&str], date: &str) {
+    let status = Command::new("git")
+        .args([
+            "-c",
+            "user.name=Tester",
+            "-c",
+            "user.email=tester@example.com",
+        ])
+        .args(args)
+        .env("GIT_AUTHOR_DATE", date)
+        .env("GIT_COMMITTER_DATE", date)
+        .current_dir(repo)
+        .output()
+        .expect("Failed to run git");
+    assert!(
+        status.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&status.stderr)
+    );
+}
+
+pub fn head_sha(repo: &Path) -> String {
+    let output = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .current_dir(repo)
+        .output()
+        .expect("Failed to run git");
+    String::from_utf8(output.stdout).unwrap().trim().to_owned()
+}
+
+pub fn write_file(repo: &Path, filepath: &str, content: &str) {
+    let filepath = repo.join(filepath);
+    if let Some(parent) = filepath.parent() {
+        std::fs::create_dir_all(parent).unwrap();
+    }
+    std::fs::write(filepath, content).unwrap();
+}
+
+pub fn read_file(repo: &Path, filepath: &str) -> String {
+    std::fs::read_to_string(repo.join(filepath)).unwrap()
+}
+
+/// Create a repository with all `files` added in a single commit on `date`.
+pub fn create_repo(files: &[(&str, &str)], date: &str) -> TempDir {
+    let repo = tempfile::tempdir().unwrap();
+    git(repo.path(), &["init", "-q"]);
+    for (filepath, content) in files {
+        write_file(repo.path(), filepath, content);
+    }
+    git_at(repo.path(), &["add", "-A"], date);
+    git_at(repo.path(), &["commit", "-q", "-m", "Initial commit"], date);
+    repo
+}
+
+pub fn repo_str(repo: &TempDir) -> &str {
+    repo.path().to_str().unwrap()
+}
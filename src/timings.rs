@@ -0,0 +1,96 @@
+//! Measure the time spent in the phases of a run.
+
+use crate::CError;
+use std::future::Future;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Phases of a run which are timed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Listing and filtering the files to check
+    ListFiles,
+    /// Looking up commit dates in the git history
+    GitHistory,
+    /// Reading, comparing and writing files
+    Files,
+}
+
+impl Phase {
+    const ALL: [Phase; 3] = [Phase::ListFiles, Phase::GitHistory, Phase::Files];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Phase::ListFiles => "List files",
+            Phase::GitHistory => "Git history",
+            Phase::Files => "Read/write files",
+        }
+    }
+}
+
+/// Time spent per phase, summed over all files.
+///
+/// Files are processed concurrently, so the time of the per-file phases can
+/// exceed the duration of the run.
+#[derive(Debug, Default)]
+pub struct Timings {
+    nanos: [AtomicU64; 3],
+}
+
+impl Timings {
+    pub fn add(&self, phase: Phase, duration: Duration) {
+        self.nanos[phase as usize].fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn get(&self, phase: Phase) -> Duration {
+        Duration::from_nanos(self.nanos[phase as usize].load(Ordering::Relaxed))
+    }
+
+    /// Await `fut` and add the time until it is ready to `phase`.
+    pub async fn time<T>(&self, phase: Phase, fut: impl Future<Output = T>) -> T {
+        let start = Instant::now();
+        let res = fut.await;
+        self.add(phase, start.elapsed());
+        res
+    }
+}
+
+pub fn write_timings(timings: &Timings, report: &mut dyn Write) -> Result<(), CError> {
+    writeln!(report, "Time per phase (summed over files):")?;
+    for phase in Phase::ALL {
+        writeln!(
+            report,
+            "{:<16} {:>8.3}s",
+            phase.label(),
+            timings.get(phase).as_secs_f32()
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{write_timings, Phase, Timings};
+    use futures::future::ready;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_timings() {
+        let timings = Timings::default();
+        timings.add(Phase::GitHistory, Duration::from_millis(1500));
+        timings.add(Phase::GitHistory, Duration::from_millis(250));
+        assert_eq!(timings.time(Phase::Files, ready(3)).await, 3);
+        assert_eq!(timings.get(Phase::GitHistory), Duration::from_millis(1750));
+        assert_eq!(timings.get(Phase::ListFiles), Duration::ZERO);
+
+        timings.add(Phase::ListFiles, Duration::from_millis(20));
+        let mut report = Vec::new();
+        write_timings(&timings, &mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.starts_with("Time per phase (summed over files):\n"));
+        assert!(report.contains("List files          0.020s\n"));
+        assert!(report.contains("Git history         1.750s\n"));
+    }
+}